@@ -0,0 +1,604 @@
+use crate::stats::Divisor;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum BeatmapError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for BeatmapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BeatmapError::Io(e) => write!(f, "Failed to read beatmap: {}", e),
+            BeatmapError::Parse { line, message } => {
+                write!(f, "Invalid beatmap at line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BeatmapError {}
+
+impl From<std::io::Error> for BeatmapError {
+    fn from(e: std::io::Error) -> Self {
+        BeatmapError::Io(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimingPoint {
+    /// Milliseconds from the start of the song.
+    pub time: f64,
+    /// Milliseconds per beat for uninherited points, a negative slider velocity multiplier otherwise.
+    pub beat_length: f64,
+    pub uninherited: bool,
+}
+
+impl TimingPoint {
+    pub fn bpm(&self) -> Option<f64> {
+        if self.uninherited && self.beat_length > 0.0 {
+            Some(60000.0 / self.beat_length)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HitObjectKind {
+    Circle,
+    Slider,
+    Spinner,
+    Hold,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HitObject {
+    /// Milliseconds from the start of the song.
+    pub time: f64,
+    pub kind: HitObjectKind,
+}
+
+impl HitObject {
+    /// Whether the object starts with a single key press. Spinners don't.
+    pub fn is_tap(&self) -> bool {
+        self.kind != HitObjectKind::Spinner
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Beatmap {
+    pub title: String,
+    pub artist: String,
+    pub version: String,
+    pub mode: u32,
+    pub od: f64,
+    pub timing_points: Vec<TimingPoint>,
+    pub hit_objects: Vec<HitObject>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Section {
+    None,
+    General,
+    Metadata,
+    Difficulty,
+    TimingPoints,
+    HitObjects,
+    Other,
+}
+
+fn parse_number(value: &str, line: usize, what: &str) -> Result<f64, BeatmapError> {
+    // NaN and infinity parse too, but can't be placed in time.
+    match value.trim().parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err(BeatmapError::Parse {
+            line,
+            message: format!("{} \"{}\" is not a number", what, value.trim()),
+        }),
+    }
+}
+
+impl Beatmap {
+    pub fn load(path: &Path) -> Result<Self, BeatmapError> {
+        Beatmap::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Reads the General, Metadata, Difficulty, TimingPoints and HitObjects sections of a `.osu` file.
+    pub fn parse(text: &str) -> Result<Self, BeatmapError> {
+        let mut beatmap = Beatmap {
+            od: 5.0,
+            ..Default::default()
+        };
+        let mut section = Section::None;
+        let mut header = false;
+
+        for (i, raw) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = raw.trim_start_matches('\u{feff}').trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            if !header {
+                if !line.starts_with("osu file format") {
+                    return Err(BeatmapError::Parse {
+                        line: line_no,
+                        message: "missing \"osu file format\" header".to_string(),
+                    });
+                }
+                header = true;
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                section = match &line[1..line.len() - 1] {
+                    "General" => Section::General,
+                    "Metadata" => Section::Metadata,
+                    "Difficulty" => Section::Difficulty,
+                    "TimingPoints" => Section::TimingPoints,
+                    "HitObjects" => Section::HitObjects,
+                    _ => Section::Other,
+                };
+                continue;
+            }
+
+            match section {
+                Section::General | Section::Metadata | Section::Difficulty => {
+                    let mut parts = line.splitn(2, ':');
+                    let key = parts.next().unwrap_or("").trim();
+                    let value = parts.next().unwrap_or("").trim();
+                    match (section, key) {
+                        (Section::General, "Mode") => {
+                            beatmap.mode = parse_number(value, line_no, "Mode")? as u32
+                        }
+                        (Section::Metadata, "Title") => beatmap.title = value.to_string(),
+                        (Section::Metadata, "Artist") => beatmap.artist = value.to_string(),
+                        (Section::Metadata, "Version") => beatmap.version = value.to_string(),
+                        // Kept to what `--od` accepts, past 10 the hit windows shrink to nothing.
+                        (Section::Difficulty, "OverallDifficulty") => {
+                            beatmap.od = parse_number(value, line_no, "OverallDifficulty")?.max(0.0).min(10.0)
+                        }
+                        _ => {}
+                    }
+                }
+                Section::TimingPoints => {
+                    let fields = line.split(',').collect::<Vec<_>>();
+                    if fields.len() < 2 {
+                        return Err(BeatmapError::Parse {
+                            line: line_no,
+                            message: "timing point needs at least a time and a beat length".to_string(),
+                        });
+                    }
+                    let time = parse_number(fields[0], line_no, "Timing point time")?;
+                    let beat_length = parse_number(fields[1], line_no, "Beat length")?;
+                    // Old formats don't have the field, and only use negative beat lengths for inherited points.
+                    let uninherited = match fields.get(6) {
+                        Some(field) => field.trim() == "1",
+                        None => beat_length > 0.0,
+                    };
+                    beatmap.timing_points.push(TimingPoint {
+                        time,
+                        beat_length,
+                        uninherited,
+                    });
+                }
+                Section::HitObjects => {
+                    let fields = line.split(',').collect::<Vec<_>>();
+                    if fields.len() < 4 {
+                        return Err(BeatmapError::Parse {
+                            line: line_no,
+                            message: "hit object needs at least x, y, time and type".to_string(),
+                        });
+                    }
+                    let time = parse_number(fields[2], line_no, "Hit object time")?;
+                    let kind_bits = parse_number(fields[3], line_no, "Hit object type")? as u32;
+                    let kind = if kind_bits & 2 != 0 {
+                        HitObjectKind::Slider
+                    } else if kind_bits & 8 != 0 {
+                        HitObjectKind::Spinner
+                    } else if kind_bits & 128 != 0 {
+                        HitObjectKind::Hold
+                    } else {
+                        HitObjectKind::Circle
+                    };
+                    beatmap.hit_objects.push(HitObject { time, kind });
+                }
+                Section::None | Section::Other => {}
+            }
+        }
+
+        if !header {
+            return Err(BeatmapError::Parse {
+                line: 1,
+                message: "file is empty".to_string(),
+            });
+        }
+        beatmap
+            .timing_points
+            .sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+        beatmap
+            .hit_objects
+            .sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+        Ok(beatmap)
+    }
+
+    pub fn name(&self) -> String {
+        format!("{} - {} [{}]", self.artist, self.title, self.version)
+    }
+
+    /// BPM of the uninherited timing point active at the given time.
+    pub fn bpm_at(&self, time: f64) -> Option<f64> {
+        let mut uninherited = self.timing_points.iter().filter(|p| p.uninherited);
+        let first = uninherited.next()?;
+        let active = uninherited
+            .take_while(|p| p.time <= time)
+            .last()
+            .unwrap_or(first);
+        active.bpm()
+    }
+
+    /// Tap chart of the objects between `from` and `to` (in milliseconds, inclusive).
+    pub fn chart(&self, from: Option<f64>, to: Option<f64>) -> Chart {
+        let notes = self
+            .hit_objects
+            .iter()
+            .filter(|o| o.is_tap())
+            .map(|o| o.time)
+            .filter(|t| from.map(|from| *t >= from).unwrap_or(true))
+            .filter(|t| to.map(|to| *t <= to).unwrap_or(true))
+            .collect::<Vec<_>>();
+        let start = notes.first().copied().unwrap_or(0.0);
+        let end = notes.last().copied().unwrap_or(0.0);
+
+        let mut bpms = Vec::new();
+        if let Some(bpm) = self.bpm_at(start) {
+            bpms.push((0.0, bpm));
+        }
+        for point in &self.timing_points {
+            if let Some(bpm) = point.bpm() {
+                if point.time > start && point.time <= end {
+                    bpms.push((point.time - start, bpm));
+                }
+            }
+        }
+
+        Chart {
+            name: self.name(),
+            notes: notes.iter().map(|t| t - start).collect(),
+            bpms,
+        }
+    }
+}
+
+/// Times to tap at, in milliseconds from the first note.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Chart {
+    pub name: String,
+    pub notes: Vec<f64>,
+    /// Beatmap BPM changes as (time, bpm), starting with the BPM at the first note.
+    pub bpms: Vec<(f64, f64)>,
+}
+
+impl Chart {
+    pub fn bpm_at(&self, time: f64) -> Option<f64> {
+        self.bpms
+            .iter()
+            .take_while(|(t, _)| *t <= time)
+            .last()
+            .or_else(|| self.bpms.first())
+            .map(|(_, bpm)| *bpm)
+    }
+
+    /// Milliseconds between the first and the last note.
+    pub fn duration(&self) -> f64 {
+        self.notes.last().copied().unwrap_or(0.0)
+    }
+}
+
+/// Longest run counted as a burst rather than a stream.
+pub const BURST_MAX_NOTES: usize = 11;
+/// Shortest run counted as a deathstream.
+pub const DEATHSTREAM_MIN_NOTES: usize = 48;
+/// Notes further apart than this (in milliseconds) are never part of a stream.
+pub const STREAM_MAX_INTERVAL: f64 = 150.0;
+/// How far an interval can be from the first one of a run, relative to it, to still be in the run.
+pub const STREAM_TOLERANCE: f64 = 0.1;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StreamKind {
+    Burst,
+    Stream,
+    Deathstream,
+}
+
+impl fmt::Display for StreamKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamKind::Burst => write!(f, "burst"),
+            StreamKind::Stream => write!(f, "stream"),
+            StreamKind::Deathstream => write!(f, "deathstream"),
+        }
+    }
+}
+
+/// A run of evenly spaced notes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stream {
+    /// Milliseconds from the start of the song to the first note.
+    pub start: f64,
+    /// Milliseconds from the start of the song to the last note.
+    pub end: f64,
+    pub notes: usize,
+    /// Average milliseconds between two notes.
+    pub interval: f64,
+}
+
+impl Stream {
+    pub fn kind(&self) -> StreamKind {
+        if self.notes <= BURST_MAX_NOTES {
+            StreamKind::Burst
+        } else if self.notes < DEATHSTREAM_MIN_NOTES {
+            StreamKind::Stream
+        } else {
+            StreamKind::Deathstream
+        }
+    }
+
+    /// Stream BPM, counting the notes at the given snap divisor.
+    pub fn bpm(&self, divisor: Divisor) -> f64 {
+        divisor.bpm(1000.0 / self.interval)
+    }
+
+    /// Speed at 1/4 weighted by length: doubling the length counts as much as the base speed.
+    pub fn difficulty(&self) -> f64 {
+        self.bpm(Divisor::default()) * (self.notes as f64).log2()
+    }
+}
+
+impl Beatmap {
+    /// Runs of at least 3 evenly spaced notes, hardest first.
+    pub fn streams(&self) -> Vec<Stream> {
+        let times = self
+            .hit_objects
+            .iter()
+            .filter(|o| o.is_tap())
+            .map(|o| o.time)
+            .collect::<Vec<_>>();
+        let mut streams = Vec::new();
+        let mut i = 0;
+        while i + 1 < times.len() {
+            let reference = times[i + 1] - times[i];
+            if reference <= 0.0 || reference > STREAM_MAX_INTERVAL {
+                i += 1;
+                continue;
+            }
+            let mut j = i + 1;
+            while j + 1 < times.len()
+                && ((times[j + 1] - times[j]) - reference).abs() <= reference * STREAM_TOLERANCE
+            {
+                j += 1;
+            }
+            let notes = j - i + 1;
+            if notes >= 3 {
+                streams.push(Stream {
+                    start: times[i],
+                    end: times[j],
+                    notes,
+                    interval: (times[j] - times[i]) / (notes - 1) as f64,
+                });
+            }
+            i = j;
+        }
+        streams.sort_by(|a, b| {
+            b.difficulty()
+                .partial_cmp(&a.difficulty())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        streams
+    }
+}
+
+/// Formats milliseconds from the start of a song like the osu! editor does.
+pub fn format_time(ms: f64) -> String {
+    let ms = ms.max(0.0) as u64;
+    format!("{}:{:02}:{:03}", ms / 60000, ms / 1000 % 60, ms % 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\u{feff}osu file format v14
+
+[General]
+Mode: 0
+
+[Metadata]
+Title:Song
+Artist:Band
+Version:Insane
+
+[Difficulty]
+OverallDifficulty:8.5
+
+[Events]
+0,0,\"bg.jpg\",0,0
+
+[TimingPoints]
+// Inherited points come before uninherited ones here on purpose.
+2000,-50,4,2,0,50,0,0
+1000,500,4,2,0,50,1,0
+3000,250,4,2,0,50,1,0
+
+[HitObjects]
+256,192,1500,5,0,0:0:0:0:
+256,192,1000,1,0,0:0:0:0:
+256,192,2000,2,0,B|300:200,1,100
+256,192,2500,12,0,3000,0:0:0:0:
+";
+
+    fn parse_error(text: &str) -> (usize, String) {
+        match Beatmap::parse(text) {
+            Err(BeatmapError::Parse { line, message }) => (line, message),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_metadata() {
+        let beatmap = Beatmap::parse(MAP).unwrap();
+        assert_eq!(beatmap.name(), "Band - Song [Insane]");
+        assert_eq!(beatmap.mode, 0);
+        assert_eq!(beatmap.od, 8.5);
+    }
+
+    #[test]
+    fn od_in_range() {
+        let od = |value: &str| {
+            Beatmap::parse(&format!("osu file format v14\n[Difficulty]\nOverallDifficulty:{}\n", value))
+                .unwrap()
+                .od
+        };
+        assert_eq!(od("0"), 0.0);
+        assert_eq!(od("10"), 10.0);
+        assert_eq!(od("14"), 10.0);
+        assert_eq!(od("-2"), 0.0);
+    }
+
+    #[test]
+    fn parse_timing_points() {
+        let beatmap = Beatmap::parse(MAP).unwrap();
+        let times = beatmap.timing_points.iter().map(|p| p.time).collect::<Vec<_>>();
+        assert_eq!(times, vec![1000.0, 2000.0, 3000.0]);
+        assert_eq!(beatmap.timing_points[0].bpm(), Some(120.0));
+        assert_eq!(beatmap.timing_points[1].bpm(), None);
+        assert_eq!(beatmap.bpm_at(0.0), Some(120.0));
+        assert_eq!(beatmap.bpm_at(2500.0), Some(120.0));
+        assert_eq!(beatmap.bpm_at(3000.0), Some(240.0));
+    }
+
+    #[test]
+    fn parse_hit_objects() {
+        let beatmap = Beatmap::parse(MAP).unwrap();
+        let objects = beatmap.hit_objects.iter().map(|o| (o.time, o.kind)).collect::<Vec<_>>();
+        assert_eq!(
+            objects,
+            vec![
+                (1000.0, HitObjectKind::Circle),
+                (1500.0, HitObjectKind::Circle),
+                (2000.0, HitObjectKind::Slider),
+                (2500.0, HitObjectKind::Spinner),
+            ]
+        );
+        // Spinners aren't tapped.
+        assert_eq!(beatmap.chart(None, None).notes, vec![0.0, 500.0, 1000.0]);
+        assert_eq!(beatmap.chart(Some(1500.0), None).notes, vec![0.0, 500.0]);
+    }
+
+    #[test]
+    fn old_timing_points_without_uninherited_field() {
+        let beatmap = Beatmap::parse("osu file format v5\n[TimingPoints]\n0,300\n100,-100\n").unwrap();
+        assert!(beatmap.timing_points[0].uninherited);
+        assert!(!beatmap.timing_points[1].uninherited);
+        assert_eq!(beatmap.bpm_at(500.0), Some(200.0));
+    }
+
+    #[test]
+    fn missing_header() {
+        assert_eq!(parse_error("[General]\nMode: 0\n").0, 1);
+        assert_eq!(parse_error("\n\n").1, "file is empty");
+    }
+
+    #[test]
+    fn malformed_lines() {
+        assert_eq!(parse_error("osu file format v14\n[TimingPoints]\n1000\n").0, 3);
+        assert_eq!(parse_error("osu file format v14\n[HitObjects]\n256,192,1000\n").0, 3);
+        let (line, message) = parse_error("osu file format v14\n\n[HitObjects]\n256,192,soon,1\n");
+        assert_eq!(line, 4);
+        assert_eq!(message, "Hit object time \"soon\" is not a number");
+        assert_eq!(parse_error("osu file format v14\n[Difficulty]\nOverallDifficulty:hard\n").0, 3);
+    }
+
+    fn beatmap(times: &[f64]) -> Beatmap {
+        Beatmap {
+            hit_objects: times
+                .iter()
+                .map(|time| HitObject {
+                    time: *time,
+                    kind: HitObjectKind::Circle,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    // Notes `interval` milliseconds apart from `start`.
+    fn run(start: f64, notes: usize, interval: f64) -> Vec<f64> {
+        (0..notes).map(|i| start + i as f64 * interval).collect()
+    }
+
+    #[test]
+    fn find_runs() {
+        let mut times = run(0.0, 5, 100.0);
+        // Too far apart to be a stream.
+        times.extend(run(2000.0, 4, 300.0));
+        // Slightly uneven, within the tolerance.
+        times.extend(vec![5000.0, 5125.0, 5245.0, 5370.0]);
+        // Speeds up past the tolerance, which ends the first run.
+        times.extend(vec![8000.0, 8100.0, 8200.0, 8250.0, 8300.0, 8350.0]);
+        let mut streams = beatmap(&times).streams();
+        streams.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+        let runs = streams.iter().map(|s| (s.start, s.end, s.notes)).collect::<Vec<_>>();
+        assert_eq!(
+            runs,
+            vec![(0.0, 400.0, 5), (5000.0, 5370.0, 4), (8000.0, 8200.0, 3), (8200.0, 8350.0, 4)]
+        );
+        assert_eq!(streams[0].interval, 100.0);
+    }
+
+    #[test]
+    fn spinners_and_short_runs_are_not_streams() {
+        let mut beatmap = beatmap(&[0.0, 100.0, 200.0, 1000.0, 1100.0]);
+        beatmap.hit_objects[1].kind = HitObjectKind::Spinner;
+        assert_eq!(beatmap.streams(), vec![]);
+    }
+
+    #[test]
+    fn classify_streams() {
+        let stream = |notes| Stream {
+            start: 0.0,
+            end: 0.0,
+            notes,
+            interval: 125.0,
+        };
+        assert_eq!(stream(3).kind(), StreamKind::Burst);
+        assert_eq!(stream(BURST_MAX_NOTES).kind(), StreamKind::Burst);
+        assert_eq!(stream(BURST_MAX_NOTES + 1).kind(), StreamKind::Stream);
+        assert_eq!(stream(DEATHSTREAM_MIN_NOTES - 1).kind(), StreamKind::Stream);
+        assert_eq!(stream(DEATHSTREAM_MIN_NOTES).kind(), StreamKind::Deathstream);
+        assert_eq!(stream(16).bpm(Divisor::default()), 120.0);
+        assert_eq!(stream(16).bpm(Divisor::new(2).unwrap()), 240.0);
+        assert_eq!(stream(16).difficulty(), 480.0);
+    }
+
+    #[test]
+    fn hardest_stream_first() {
+        let mut times = run(0.0, 16, 100.0);
+        times.extend(run(5000.0, 64, 120.0));
+        times.extend(run(20000.0, 8, 80.0));
+        let streams = beatmap(&times).streams();
+        let starts = streams.iter().map(|s| s.start).collect::<Vec<_>>();
+        assert_eq!(starts, vec![5000.0, 0.0, 20000.0]);
+    }
+
+    #[test]
+    fn non_finite_numbers() {
+        for time in &["NaN", "inf", "-infinity"] {
+            let text = format!("osu file format v14\n[HitObjects]\n256,192,1000,1\n256,192,{},1\n", time);
+            assert_eq!(parse_error(&text).0, 4, "{}", time);
+            let text = format!("osu file format v14\n[TimingPoints]\n{},500,4,2,0,50,1,0\n", time);
+            assert_eq!(parse_error(&text).0, 3, "{}", time);
+        }
+    }
+}
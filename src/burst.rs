@@ -0,0 +1,139 @@
+use crate::stats::{Divisor, IntervalStats, Stats};
+
+/// Tap counts offered in the menu.
+pub const BURST_PRESETS: [u32; 3] = [16, 32, 64];
+pub const DEFAULT_BURST: u32 = 32;
+
+// Consecutive intervals the peak speed is measured over.
+const PEAK_WINDOW: usize = 8;
+
+/// How quickly a fixed number of taps was made, like the usual click tests.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BurstReport {
+    pub taps: u32,
+    /// Seconds from the first to the last tap.
+    pub time: f64,
+    pub bpm: Option<f64>,
+    /// Fastest BPM over `PEAK_WINDOW` intervals in a row.
+    pub peak_bpm: Option<f64>,
+    pub unstable_rate: Option<f64>,
+}
+
+/// Whether a burst of the given number of taps is over.
+pub fn is_complete(stats: &Stats, taps: u32) -> bool {
+    stats.total >= taps
+}
+
+impl BurstReport {
+    pub fn new(stats: &Stats, divisor: Divisor) -> Self {
+        let time = match (stats.first_tap, stats.last_tap) {
+            (Some(first), Some(last)) => last.duration_since(first).as_secs_f64(),
+            _ => 0.0,
+        };
+        let bpm = if stats.total > 1 && time > 0.0 {
+            Some(divisor.bpm((stats.total - 1) as f64 / time))
+        } else {
+            None
+        };
+        let intervals = &stats.session_intervals;
+        let window = PEAK_WINDOW.min(intervals.len());
+        let peak_bpm = if window == 0 {
+            None
+        } else {
+            intervals
+                .windows(window)
+                .map(|w| w.iter().sum::<f64>())
+                .fold(None, |fastest: Option<f64>, sum| Some(fastest.map_or(sum, |f| f.min(sum))))
+                .map(|sum| divisor.bpm(1000.0 * window as f64 / sum))
+        };
+        BurstReport {
+            taps: stats.total,
+            time,
+            bpm,
+            peak_bpm,
+            unstable_rate: IntervalStats::from_intervals(intervals).map(|i| i.unstable_rate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    // Stats of taps at the given milliseconds after the start.
+    fn stats(times: &[u64]) -> Stats {
+        let start = Instant::now();
+        let mut stats = Stats::default();
+        for pair in times.windows(2) {
+            stats.session_intervals.push((pair[1] - pair[0]) as f64);
+        }
+        stats.total = times.len() as u32;
+        stats.first_tap = times.first().map(|ms| start + Duration::from_millis(*ms));
+        stats.last_tap = times.last().map(|ms| start + Duration::from_millis(*ms));
+        stats
+    }
+
+    #[test]
+    fn over_after_the_taps() {
+        let times = (0..32).map(|i| i * 100).collect::<Vec<_>>();
+        assert!(!is_complete(&stats(&times[..31]), 32));
+        assert!(is_complete(&stats(&times), 32));
+        assert!(is_complete(&stats(&times), 16));
+        assert!(!is_complete(&Stats::default(), 1));
+    }
+
+    #[test]
+    fn steady_burst() {
+        // 16 taps, 100ms apart.
+        let times = (0..16).map(|i| i * 100).collect::<Vec<_>>();
+        let report = BurstReport::new(&stats(&times), Divisor::default());
+        assert_eq!(report.taps, 16);
+        assert!(close(report.time, 1.5));
+        assert!(close(report.bpm.unwrap(), 150.0));
+        assert!(close(report.peak_bpm.unwrap(), 150.0));
+        assert!(close(report.unstable_rate.unwrap(), 0.0));
+    }
+
+    #[test]
+    fn uneven_burst() {
+        let report = BurstReport::new(&stats(&[0, 90, 200, 290, 400]), Divisor::new(2).unwrap());
+        assert!(close(report.bpm.unwrap(), 300.0));
+        // Intervals of 90 and 110ms, 10ms from their mean.
+        assert!(close(report.unstable_rate.unwrap(), 100.0));
+    }
+
+    #[test]
+    fn peak_over_the_fastest_taps() {
+        // Eight intervals at 80ms in the middle of 100ms ones.
+        let mut times = vec![0];
+        for interval in [100; 6].iter().chain(&[80; PEAK_WINDOW]).chain(&[100; 6]) {
+            times.push(times.last().unwrap() + interval);
+        }
+        let report = BurstReport::new(&stats(&times), Divisor::default());
+        assert!(close(report.peak_bpm.unwrap(), 187.5));
+        assert!(report.bpm.unwrap() < 170.0);
+
+        // Shorter bursts than the peak window are measured whole.
+        let report = BurstReport::new(&stats(&[0, 80, 200]), Divisor::default());
+        assert!(close(report.peak_bpm.unwrap(), 150.0));
+        assert!(close(report.bpm.unwrap(), 150.0));
+    }
+
+    #[test]
+    fn too_few_taps() {
+        for times in &[&[][..], &[0][..]] {
+            let report = BurstReport::new(&stats(times), Divisor::default());
+            assert_eq!(report.time, 0.0);
+            assert_eq!((report.bpm, report.peak_bpm, report.unstable_rate), (None, None, None));
+        }
+        // A single interval has a speed but no spread.
+        let report = BurstReport::new(&stats(&[0, 125]), Divisor::default());
+        assert!(close(report.bpm.unwrap(), 120.0));
+        assert_eq!(report.unstable_rate, None);
+    }
+}
@@ -0,0 +1,25 @@
+use std::time::Instant;
+
+/// Where the systems and states get the current time from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Clock {
+    /// The monotonic system clock.
+    Monotonic,
+    /// Stands still at the given time, to script the taps of tests.
+    Manual(Instant),
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock::Monotonic
+    }
+}
+
+impl Clock {
+    pub fn now(&self) -> Instant {
+        match self {
+            Clock::Monotonic => Instant::now(),
+            Clock::Manual(time) => *time,
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use crate::stats::Stats;
+use std::fmt;
+
+/// Hits the current drift is averaged over.
+pub const DRIFT_WINDOW: usize = 16;
+
+// Milliseconds off the beat on average before it counts as rushing or dragging.
+const DRIFT_THRESHOLD: f64 = 5.0;
+
+/// Which side of the beat the taps lean towards.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Tendency {
+    Rushing,
+    OnTime,
+    Dragging,
+}
+
+impl Tendency {
+    /// Tendency of a mean offset in milliseconds.
+    pub fn of(offset: f64) -> Self {
+        if offset < -DRIFT_THRESHOLD {
+            Tendency::Rushing
+        } else if offset > DRIFT_THRESHOLD {
+            Tendency::Dragging
+        } else {
+            Tendency::OnTime
+        }
+    }
+}
+
+impl fmt::Display for Tendency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tendency::Rushing => write!(f, "rushing"),
+            Tendency::OnTime => write!(f, "on time"),
+            Tendency::Dragging => write!(f, "dragging"),
+        }
+    }
+}
+
+fn mean(offsets: &[f64]) -> Option<f64> {
+    if offsets.is_empty() {
+        None
+    } else {
+        Some(offsets.iter().sum::<f64>() / offsets.len() as f64)
+    }
+}
+
+/// Mean offset of the last `DRIFT_WINDOW` hits, once there are that many.
+pub fn recent_drift(stats: &Stats) -> Option<f64> {
+    let offsets = &stats.hit_offsets;
+    if offsets.len() < DRIFT_WINDOW {
+        return None;
+    }
+    mean(&offsets[offsets.len() - DRIFT_WINDOW..])
+}
+
+/// Whether the taps moved away from the beat over the session, which UR alone doesn't show.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DriftReport {
+    /// Mean offset of the first and the last `DRIFT_WINDOW` hits.
+    pub start: f64,
+    pub end: f64,
+    /// Mean offset of all the hits.
+    pub overall: f64,
+}
+
+impl DriftReport {
+    /// `None` until there are enough hits to compare the start with the end.
+    pub fn new(stats: &Stats) -> Option<Self> {
+        let offsets = &stats.hit_offsets;
+        if offsets.len() < DRIFT_WINDOW * 2 {
+            return None;
+        }
+        Some(DriftReport {
+            start: mean(&offsets[..DRIFT_WINDOW])?,
+            end: mean(&offsets[offsets.len() - DRIFT_WINDOW..])?,
+            overall: mean(offsets)?,
+        })
+    }
+
+    /// Which way the taps moved from the start to the end.
+    pub fn trend(&self) -> Tendency {
+        Tendency::of(self.end - self.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hits(offsets: impl Iterator<Item = f64>) -> Stats {
+        Stats {
+            hit_offsets: offsets.collect(),
+            ..Default::default()
+        }
+    }
+
+    // 48 hits going from on the beat to `to` milliseconds off it, a little uneven.
+    fn drifting(to: f64) -> Stats {
+        hits((0..48).map(|i| to * i as f64 / 47.0 + if i % 2 == 0 { -3.0 } else { 3.0 }))
+    }
+
+    #[test]
+    fn tendencies() {
+        assert_eq!(Tendency::of(-5.5), Tendency::Rushing);
+        assert_eq!(Tendency::of(-5.0), Tendency::OnTime);
+        assert_eq!(Tendency::of(5.0), Tendency::OnTime);
+        assert_eq!(Tendency::of(5.5), Tendency::Dragging);
+    }
+
+    #[test]
+    fn steady_player() {
+        let stats = drifting(0.0);
+        assert_eq!(recent_drift(&stats), Some(0.0));
+        let report = DriftReport::new(&stats).unwrap();
+        assert_eq!((report.start, report.end, report.overall), (0.0, 0.0, 0.0));
+        assert_eq!(report.trend(), Tendency::OnTime);
+    }
+
+    #[test]
+    fn drifting_early() {
+        let stats = drifting(-20.0);
+        assert_eq!(recent_drift(&stats).map(Tendency::of), Some(Tendency::Rushing));
+        let report = DriftReport::new(&stats).unwrap();
+        assert!(report.start > -5.0 && report.end < -15.0);
+        assert!((report.overall + 10.0).abs() < 1e-9);
+        assert_eq!(report.trend(), Tendency::Rushing);
+    }
+
+    #[test]
+    fn drifting_late() {
+        let stats = drifting(20.0);
+        assert_eq!(recent_drift(&stats).map(Tendency::of), Some(Tendency::Dragging));
+        let report = DriftReport::new(&stats).unwrap();
+        assert!(report.start < 5.0 && report.end > 15.0);
+        assert_eq!(report.trend(), Tendency::Dragging);
+
+        // Late all along isn't drifting.
+        let report = DriftReport::new(&hits((0..48).map(|_| 20.0))).unwrap();
+        assert_eq!(report.overall, 20.0);
+        assert_eq!(report.trend(), Tendency::OnTime);
+    }
+
+    #[test]
+    fn needs_enough_hits() {
+        let few = hits((0..DRIFT_WINDOW - 1).map(|_| 10.0));
+        assert_eq!(recent_drift(&few), None);
+        assert_eq!(DriftReport::new(&few), None);
+        let some = hits((0..DRIFT_WINDOW * 2 - 1).map(|i| i as f64));
+        // Only the latest hits count.
+        assert_eq!(recent_drift(&some), Some(22.5));
+        assert_eq!(DriftReport::new(&some), None);
+    }
+}
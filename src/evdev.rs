@@ -0,0 +1,433 @@
+use crate::input::Binding;
+use easycurses::Input;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem::size_of;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// The seconds and microseconds of a `struct timeval` are `long`s, which are as wide as pointers on Linux.
+const LONG_SIZE: usize = size_of::<isize>();
+/// Size of a `struct input_event`: a `timeval`, then type, code and value.
+pub const EVENT_SIZE: usize = 2 * LONG_SIZE + 8;
+
+const EV_KEY: u16 = 1;
+// Values of key events. Repeats are left out, a held key is a single tap.
+const KEY_RELEASE: i32 = 0;
+const KEY_PRESS: i32 = 1;
+
+// Linux key codes of the rows of a US QWERTY keyboard, unshifted, each from the code of its first key.
+const ROWS: [(u16, &str); 4] = [(2, "1234567890-="), (16, "qwertyuiop[]"), (30, "asdfghjkl;'`"), (43, "\\zxcvbnm,./")];
+const NAMED_CODES: [(u16, Input); 27] = [
+    (1, Input::Character('\u{1b}')),
+    (15, Input::Character('\t')),
+    (28, Input::Character('\n')),
+    (57, Input::Character(' ')),
+    (14, Input::KeyBackspace),
+    (103, Input::KeyUp),
+    (105, Input::KeyLeft),
+    (106, Input::KeyRight),
+    (108, Input::KeyDown),
+    (59, Input::KeyF1),
+    (60, Input::KeyF2),
+    (61, Input::KeyF3),
+    (62, Input::KeyF4),
+    (63, Input::KeyF5),
+    (64, Input::KeyF6),
+    (65, Input::KeyF7),
+    (66, Input::KeyF8),
+    (67, Input::KeyF9),
+    (68, Input::KeyF10),
+    (87, Input::KeyF11),
+    (88, Input::KeyF12),
+    // The keypad keys curses names, as they are with num lock off.
+    (71, Input::KeyA1),
+    (73, Input::KeyA3),
+    (76, Input::KeyB2),
+    (79, Input::KeyC1),
+    (81, Input::KeyC3),
+    (96, Input::KeyEnter),
+];
+// Past the last key code.
+const KEY_CNT: u16 = 0x300;
+// BTN_LEFT, BTN_RIGHT and BTN_MIDDLE, as numbered by curses.
+const MOUSE_CODES: [(u16, u8); 3] = [(0x110, 1), (0x111, 3), (0x112, 2)];
+
+/// A `struct input_event`, with the time it was stamped with by the kernel.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RawEvent {
+    /// Time since the epoch.
+    pub time: Duration,
+    pub kind: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl RawEvent {
+    pub fn parse(bytes: &[u8; EVENT_SIZE]) -> Self {
+        const T: usize = 2 * LONG_SIZE;
+        let sec = isize::from_ne_bytes(bytes[0..LONG_SIZE].try_into().unwrap());
+        let usec = isize::from_ne_bytes(bytes[LONG_SIZE..T].try_into().unwrap());
+        RawEvent {
+            time: Duration::from_secs(sec.max(0) as u64) + Duration::from_micros(usec.max(0) as u64),
+            kind: u16::from_ne_bytes(bytes[T..T + 2].try_into().unwrap()),
+            code: u16::from_ne_bytes(bytes[T + 2..T + 4].try_into().unwrap()),
+            value: i32::from_ne_bytes(bytes[T + 4..T + 8].try_into().unwrap()),
+        }
+    }
+}
+
+/// The binding a key code stands for in the keymap, if it has one.
+pub fn binding(code: u16) -> Option<Binding> {
+    let in_row = |(first, keys): (u16, &str)| {
+        code.checked_sub(first)
+            .and_then(|i| keys.chars().nth(i as usize))
+            .map(|c| Binding::Input(Input::Character(c)))
+    };
+    if let Some(binding) = ROWS.iter().cloned().find_map(in_row) {
+        return Some(binding);
+    }
+    if let Some((_, input)) = NAMED_CODES.iter().find(|(c, _)| *c == code) {
+        return Some(Binding::Input(*input));
+    }
+    MOUSE_CODES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, button)| Binding::Mouse(*button))
+}
+
+/// Whether some key of an input device stands for the binding. Shifted characters don't, for one.
+pub fn has_code(binding: Binding) -> bool {
+    (0..KEY_CNT).any(|code| self::binding(code) == Some(binding))
+}
+
+/// A key going down or up.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct KeyEvent {
+    pub binding: Binding,
+    pub pressed: bool,
+    pub time: Instant,
+}
+
+impl KeyEvent {
+    fn new(event: &RawEvent, time: Instant) -> Option<Self> {
+        let pressed = match (event.kind, event.value) {
+            (EV_KEY, KEY_PRESS) => true,
+            (EV_KEY, KEY_RELEASE) => false,
+            _ => return None,
+        };
+        Some(KeyEvent {
+            binding: binding(event.code)?,
+            pressed,
+            time,
+        })
+    }
+}
+
+/// Reads the next event, `None` at the end of a recording.
+fn read_event(file: &mut File) -> io::Result<Option<RawEvent>> {
+    let mut bytes = [0; EVENT_SIZE];
+    match file.read_exact(&mut bytes) {
+        Ok(()) => Ok(Some(RawEvent::parse(&bytes))),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// The kernel stamps events with the wall clock, this moves them onto the monotonic one.
+fn device_time(event: &RawEvent) -> Instant {
+    let now = Instant::now();
+    let age = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .and_then(|wall| wall.checked_sub(event.time))
+        .unwrap_or_default();
+    now.checked_sub(age).unwrap_or(now)
+}
+
+fn read_device(mut file: File, sender: Sender<KeyEvent>) -> io::Result<()> {
+    while let Some(event) = read_event(&mut file)? {
+        if let Some(key) = KeyEvent::new(&event, device_time(&event)) {
+            if sender.send(key).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Events of a recorded device, played back with the gaps between them.
+struct Recording {
+    events: Vec<RawEvent>,
+    /// When the first event is played, once the recording was started.
+    start: Option<Instant>,
+    next: usize,
+}
+
+impl Recording {
+    fn read(mut file: File) -> io::Result<Self> {
+        let mut events = Vec::new();
+        while let Some(event) = read_event(&mut file)? {
+            events.push(event);
+        }
+        Ok(Recording {
+            events,
+            start: None,
+            next: 0,
+        })
+    }
+
+    fn drain(&mut self, until: Instant) -> Vec<KeyEvent> {
+        let (start, first) = match (self.start, self.events.first()) {
+            (Some(start), Some(first)) => (start, first.time),
+            _ => return Vec::new(),
+        };
+        let mut keys = Vec::new();
+        while let Some(event) = self.events.get(self.next) {
+            let time = start + event.time.checked_sub(first).unwrap_or_default();
+            if time > until {
+                break;
+            }
+            self.next += 1;
+            keys.extend(KeyEvent::new(event, time));
+        }
+        keys
+    }
+}
+
+enum Source {
+    Device(Receiver<KeyEvent>),
+    Recording(Recording),
+}
+
+#[cfg(unix)]
+fn is_device(file: &File) -> io::Result<bool> {
+    use std::os::unix::fs::FileTypeExt;
+    Ok(file.metadata()?.file_type().is_char_device())
+}
+
+#[cfg(not(unix))]
+fn is_device(_: &File) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Key presses and releases read from a Linux input device such as `/dev/input/event3`, or from a recording of one
+/// made with `cat /dev/input/event3 > keys.bin`. Recordings stay silent until they are started, once per session.
+pub struct Evdev {
+    pub path: String,
+    source: Mutex<Source>,
+    /// Why reading stopped early, if it did.
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl Evdev {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let error = Arc::new(Mutex::new(None));
+        let source = if is_device(&file)? {
+            let (sender, receiver) = channel();
+            let error = error.clone();
+            // Never joined: reading a device blocks until the next event, so the thread ends with the program.
+            thread::Builder::new().name("evdev".to_string()).spawn(move || {
+                if let Err(e) = read_device(file, sender) {
+                    *error.lock().unwrap() = Some(e.to_string());
+                }
+            })?;
+            Source::Device(receiver)
+        } else {
+            Source::Recording(Recording::read(file)?)
+        };
+        Ok(Evdev {
+            path: path.to_string(),
+            source: Mutex::new(source),
+            error,
+        })
+    }
+
+    pub fn is_recording(&self) -> bool {
+        match *self.source.lock().unwrap() {
+            Source::Device(_) => false,
+            Source::Recording(_) => true,
+        }
+    }
+
+    /// Plays a recording over from its first event, at `start`. Devices just keep going.
+    pub fn restart(&self, start: Instant) {
+        if let Source::Recording(recording) = &mut *self.source.lock().unwrap() {
+            recording.start = Some(start);
+            recording.next = 0;
+        }
+    }
+
+    /// Pushes the rest of a recording back, to make up for a pause.
+    pub fn delay(&self, by: Duration) {
+        if let Source::Recording(Recording { start: Some(start), .. }) = &mut *self.source.lock().unwrap() {
+            *start += by;
+        }
+    }
+
+    /// Everything read since the last call, oldest first. Recordings only go as far as `until`.
+    pub fn drain(&self, until: Instant) -> Vec<KeyEvent> {
+        match &mut *self.source.lock().unwrap() {
+            Source::Device(receiver) => receiver.try_iter().collect(),
+            Source::Recording(recording) => recording.drain(until),
+        }
+    }
+
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn record(sec: isize, usec: isize, kind: u16, code: u16, value: i32) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(EVENT_SIZE);
+        bytes.extend_from_slice(&sec.to_ne_bytes());
+        bytes.extend_from_slice(&usec.to_ne_bytes());
+        bytes.extend_from_slice(&kind.to_ne_bytes());
+        bytes.extend_from_slice(&code.to_ne_bytes());
+        bytes.extend_from_slice(&value.to_ne_bytes());
+        bytes
+    }
+
+    // Z down, held long enough to repeat, Z up, then a left click, each followed by a SYN_REPORT.
+    fn fixture() -> Vec<u8> {
+        [
+            record(1000, 250_000, EV_KEY, 44, KEY_PRESS),
+            record(1000, 250_000, 0, 0, 0),
+            record(1000, 500_000, EV_KEY, 44, 2),
+            record(1000, 500_000, 0, 0, 0),
+            record(1000, 550_000, EV_KEY, 44, KEY_RELEASE),
+            record(1000, 550_000, 0, 0, 0),
+            record(1001, 50_000, EV_KEY, 0x110, KEY_PRESS),
+            record(1001, 50_000, 0, 0, 0),
+        ]
+        .concat()
+    }
+
+    fn write_fixture(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("osu_practice_evdev_{}_{}.bin", name, std::process::id()));
+        File::create(&path).unwrap().write_all(&fixture()).unwrap();
+        path
+    }
+
+    #[test]
+    fn parse() {
+        let bytes = record(1000, 250_000, EV_KEY, 44, KEY_PRESS);
+        assert_eq!(bytes.len(), EVENT_SIZE);
+        assert_eq!(
+            RawEvent::parse(bytes[..].try_into().unwrap()),
+            RawEvent {
+                time: Duration::from_millis(1_000_250),
+                kind: EV_KEY,
+                code: 44,
+                value: KEY_PRESS,
+            }
+        );
+    }
+
+    #[test]
+    fn bindings() {
+        let character = |c| Some(Binding::Input(Input::Character(c)));
+        assert_eq!(binding(16), character('q'));
+        assert_eq!(binding(38), character('l'));
+        assert_eq!(binding(50), character('m'));
+        assert_eq!(binding(2), character('1'));
+        assert_eq!(binding(11), character('0'));
+        assert_eq!(binding(57), character(' '));
+        assert_eq!(binding(105), Some(Binding::Input(Input::KeyLeft)));
+        assert_eq!(binding(12), character('-'));
+        assert_eq!(binding(27), character(']'));
+        assert_eq!(binding(39), character(';'));
+        assert_eq!(binding(43), character('\\'));
+        assert_eq!(binding(53), character('/'));
+        assert_eq!(binding(59), Some(Binding::Input(Input::KeyF1)));
+        assert_eq!(binding(88), Some(Binding::Input(Input::KeyF12)));
+        assert_eq!(binding(76), Some(Binding::Input(Input::KeyB2)));
+        assert_eq!(binding(96), Some(Binding::Input(Input::KeyEnter)));
+        assert_eq!(binding(0x111), Some(Binding::Mouse(3)));
+        assert_eq!(binding(0), None);
+        assert_eq!(binding(29), None);
+    }
+
+    #[test]
+    fn bindable_keys_have_codes() {
+        let mut names = (1..=12).map(|n| format!("f{}", n)).collect::<Vec<_>>();
+        names.extend("abcdefghijklmnopqrstuvwxyz0123456789-=[];'`\\,./".chars().map(|c| c.to_string()));
+        for name in &[
+            "space", "tab", "enter", "backspace", "keypad_1", "keypad_3", "keypad_5", "keypad_7", "keypad_9",
+            "keypad_enter", "arrow_up", "arrow_down", "arrow_left", "arrow_right", "mouse_left", "mouse_middle",
+            "mouse_right",
+        ] {
+            names.push(name.to_string());
+        }
+        for name in names {
+            assert!(has_code(Binding::from_name(&name).unwrap()), "{}", name);
+        }
+        for shifted in &["X", "!", ":", "?"] {
+            assert!(!has_code(Binding::from_name(shifted).unwrap()), "{}", shifted);
+        }
+    }
+
+    #[test]
+    fn read_events() {
+        let path = write_fixture("read");
+        let mut file = File::open(&path).unwrap();
+        let start = Instant::now();
+        let mut keys = Vec::new();
+        let mut events = 0;
+        while let Some(event) = read_event(&mut file).unwrap() {
+            events += 1;
+            keys.extend(KeyEvent::new(&event, start));
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(events, 8);
+        // Repeats and SYN_REPORTs are dropped.
+        let z = Binding::Input(Input::Character('z'));
+        assert_eq!(
+            keys.iter().map(|key| (key.binding, key.pressed)).collect::<Vec<_>>(),
+            vec![(z, true), (z, false), (Binding::Mouse(1), true)]
+        );
+    }
+
+    #[test]
+    fn recordings_play_from_their_start() {
+        let path = write_fixture("play");
+        let evdev = Evdev::open(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(evdev.is_recording());
+        let start = Instant::now();
+        let ms = |ms| start + Duration::from_millis(ms);
+        assert_eq!(evdev.drain(ms(10_000)), Vec::new());
+
+        evdev.restart(start);
+        let first = evdev.drain(ms(299));
+        assert_eq!(first.len(), 1);
+        assert!(first[0].pressed);
+        assert_eq!(first[0].time, start);
+        // Paused for a second, the rest comes a second later.
+        evdev.delay(Duration::from_secs(1));
+        assert_eq!(evdev.drain(ms(1299)), Vec::new());
+        let rest = evdev.drain(ms(10_000));
+        assert_eq!(
+            rest.iter().map(|key| (key.binding, key.pressed, key.time)).collect::<Vec<_>>(),
+            vec![
+                (Binding::Input(Input::Character('z')), false, ms(1300)),
+                (Binding::Mouse(1), true, ms(1800)),
+            ]
+        );
+        assert_eq!(evdev.drain(ms(20_000)), Vec::new());
+
+        // Every session plays it over.
+        let again = Instant::now();
+        evdev.restart(again);
+        assert_eq!(evdev.drain(again + Duration::from_secs(1)).len(), 3);
+    }
+}
@@ -0,0 +1,181 @@
+use easycurses::{ColorPair, EasyCurses};
+
+#[derive(Copy, Clone, PartialEq)]
+struct Cell {
+    character: char,
+    color: ColorPair,
+    bold: bool,
+}
+
+/// Characters next to each other on a row, drawn the same way.
+struct Run {
+    row: i32,
+    col: i32,
+    color: ColorPair,
+    bold: bool,
+    text: String,
+}
+
+/// A screen drawn in memory, so the terminal only has to be held while it is copied over.
+/// Draws like `EasyCurses`, except that nothing past its edges is kept.
+pub struct Frame {
+    rows: usize,
+    cols: usize,
+    cells: Vec<Cell>,
+    row: i32,
+    col: i32,
+    color: ColorPair,
+    bold: bool,
+}
+
+impl Frame {
+    /// A frame filled with blanks of the given colors.
+    pub fn new(rows: usize, cols: usize, color: ColorPair) -> Self {
+        let blank = Cell {
+            character: ' ',
+            color,
+            bold: false,
+        };
+        Frame {
+            rows,
+            cols,
+            cells: vec![blank; rows * cols],
+            row: 0,
+            col: 0,
+            color,
+            bold: false,
+        }
+    }
+
+    pub fn move_rc(&mut self, row: i32, col: i32) {
+        self.row = row;
+        self.col = col;
+    }
+
+    pub fn set_color_pair(&mut self, color: ColorPair) {
+        self.color = color;
+    }
+
+    pub fn set_bold(&mut self, bold: bool) {
+        self.bold = bold;
+    }
+
+    pub fn print_char(&mut self, character: char) {
+        if self.row >= 0 && self.col >= 0 && (self.row as usize) < self.rows && (self.col as usize) < self.cols {
+            self.cells[self.row as usize * self.cols + self.col as usize] = Cell {
+                character,
+                color: self.color,
+                bold: self.bold,
+            };
+        }
+        self.col += 1;
+    }
+
+    pub fn print<S: AsRef<str>>(&mut self, text: S) {
+        for character in text.as_ref().chars() {
+            self.print_char(character);
+        }
+    }
+
+    // Whatever fits in `rows` and `cols`, as few runs as the colors allow.
+    fn runs(&self, rows: usize, cols: usize) -> Vec<Run> {
+        let mut runs = Vec::new();
+        for (row, cells) in self.cells.chunks(self.cols.max(1)).take(rows).enumerate() {
+            let mut run: Option<Run> = None;
+            for (col, cell) in cells.iter().take(cols).enumerate() {
+                match run.as_mut() {
+                    Some(run) if run.color == cell.color && run.bold == cell.bold => run.text.push(cell.character),
+                    _ => {
+                        runs.extend(run.take());
+                        run = Some(Run {
+                            row: row as i32,
+                            col: col as i32,
+                            color: cell.color,
+                            bold: cell.bold,
+                            text: cell.character.to_string(),
+                        });
+                    }
+                }
+            }
+            runs.extend(run);
+        }
+        runs
+    }
+
+    /// Replaces what the terminal shows with this frame.
+    pub fn render(&self, curses: &mut EasyCurses) {
+        let (rows, cols) = curses.get_row_col_count();
+        for run in self.runs(rows.max(0) as usize, cols.max(0) as usize) {
+            curses.move_rc(run.row, run.col);
+            curses.set_color_pair(run.color);
+            curses.set_bold(run.bold);
+            curses.print(run.text);
+        }
+        curses.set_bold(false);
+        curses.refresh();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use easycurses::Color;
+
+    fn text(frame: &Frame, rows: usize, cols: usize) -> Vec<(i32, i32, bool, String)> {
+        frame
+            .runs(rows, cols)
+            .into_iter()
+            .map(|run| (run.row, run.col, run.bold, run.text))
+            .collect()
+    }
+
+    #[test]
+    fn blank() {
+        let frame = Frame::new(2, 3, ColorPair::new(Color::White, Color::Black));
+        assert_eq!(
+            text(&frame, 2, 3),
+            vec![(0, 0, false, "   ".to_string()), (1, 0, false, "   ".to_string())]
+        );
+    }
+
+    #[test]
+    fn runs_split_where_the_colors_change() {
+        let normal = ColorPair::new(Color::White, Color::Black);
+        let mut frame = Frame::new(1, 8, normal);
+        frame.move_rc(0, 1);
+        frame.print("ab");
+        frame.set_color_pair(ColorPair::new(Color::Red, Color::Black));
+        frame.print_char('c');
+        frame.set_color_pair(normal);
+        frame.set_bold(true);
+        frame.print("d");
+        assert_eq!(
+            text(&frame, 1, 8),
+            vec![
+                (0, 0, false, " ab".to_string()),
+                (0, 3, false, "c".to_string()),
+                (0, 4, true, "d".to_string()),
+                (0, 5, false, "   ".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn drawing_past_the_edges_is_dropped() {
+        let mut frame = Frame::new(2, 4, ColorPair::new(Color::White, Color::Black));
+        frame.move_rc(0, 2);
+        frame.print("abcdef");
+        frame.move_rc(-1, 0);
+        frame.print("x");
+        frame.move_rc(5, 0);
+        frame.print("y");
+        frame.move_rc(1, -2);
+        frame.print("zzw");
+        assert_eq!(
+            text(&frame, 2, 4),
+            vec![(0, 0, false, "  ab".to_string()), (1, 0, false, "w   ".to_string())]
+        );
+        // A smaller terminal only gets what fits.
+        assert_eq!(text(&frame, 1, 3), vec![(0, 0, false, "  a".to_string())]);
+    }
+}
@@ -0,0 +1,480 @@
+use crate::stats::{Divisor, IntervalStats, Stats};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HEADER: &str = "# osu_practice history 1";
+
+/// Personal bests are kept separately for every this many BPM.
+pub const BPM_BUCKET_SIZE: u32 = 10;
+
+#[derive(Debug)]
+pub enum HistoryError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+    NoDataDir,
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryError::Io(e) => write!(f, "Failed to access history: {}", e),
+            HistoryError::Parse { line, message } => {
+                write!(f, "Invalid history at line {}: {}", line, message)
+            }
+            HistoryError::NoDataDir => write!(f, "Neither XDG_DATA_HOME nor HOME is set"),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+impl From<std::io::Error> for HistoryError {
+    fn from(e: std::io::Error) -> Self {
+        HistoryError::Io(e)
+    }
+}
+
+/// `$XDG_DATA_HOME/osu_practice`, or `~/.local/share/osu_practice`.
+pub fn data_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(base.join("osu_practice"))
+}
+
+pub fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Formats a unix timestamp as `YYYY-MM-DD` (UTC).
+pub fn format_date(timestamp: u64) -> String {
+    // Howard Hinnant's civil_from_days.
+    let z = (timestamp / 86400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// What is kept about a session once it's over.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionSummary {
+    /// Unix timestamp of the end of the session.
+    pub date: u64,
+    pub mode: String,
+    /// BPM the mode asked for, if any.
+    pub target_bpm: Option<f64>,
+    /// Seconds from the first to the last tap.
+    pub duration: f64,
+    pub taps: u32,
+    pub max_combo: u32,
+    pub score: u64,
+    pub avg_bpm: Option<f64>,
+    pub peak_bpm: Option<f64>,
+    pub unstable_rate: Option<f64>,
+    pub accuracy: Option<f64>,
+}
+
+impl SessionSummary {
+    pub fn new(stats: &Stats, divisor: Divisor, mode: &str, target_bpm: Option<f64>) -> Self {
+        let duration = match (stats.first_tap, stats.last_tap) {
+            (Some(first), Some(last)) => last.duration_since(first).as_secs_f64(),
+            _ => 0.0,
+        };
+        let avg_bpm = if stats.session_intervals.is_empty() {
+            None
+        } else {
+            let mean = stats.session_intervals.iter().sum::<f64>() / stats.session_intervals.len() as f64;
+            Some(divisor.bpm(1000.0 / mean))
+        };
+        SessionSummary {
+            date: now_unix(),
+            mode: mode.to_string(),
+            target_bpm,
+            duration,
+            taps: stats.total,
+            max_combo: stats.max_combo,
+            score: stats.score,
+            avg_bpm,
+            peak_bpm: stats.peak_bpm,
+            unstable_rate: IntervalStats::from_intervals(&stats.session_intervals).map(|i| i.unstable_rate),
+            accuracy: stats.judgements.accuracy(),
+        }
+    }
+
+    /// The BPM personal bests are grouped by: the target if there is one, what was played otherwise.
+    pub fn bpm_bucket(&self) -> Option<u32> {
+        self.target_bpm
+            .or(self.avg_bpm)
+            .map(|bpm| (bpm as u32) / BPM_BUCKET_SIZE * BPM_BUCKET_SIZE)
+    }
+
+    fn to_line(&self) -> String {
+        fn opt(value: Option<f64>) -> String {
+            value.map(|v| v.to_string()).unwrap_or_else(|| "-".to_string())
+        }
+        [
+            self.date.to_string(),
+            self.mode.clone(),
+            opt(self.target_bpm),
+            self.duration.to_string(),
+            self.taps.to_string(),
+            self.max_combo.to_string(),
+            self.score.to_string(),
+            opt(self.avg_bpm),
+            opt(self.peak_bpm),
+            opt(self.unstable_rate),
+            opt(self.accuracy),
+        ]
+        .join("\t")
+    }
+
+    fn from_line(line: &str, line_no: usize) -> Result<Self, HistoryError> {
+        let err = |message: String| HistoryError::Parse {
+            line: line_no,
+            message,
+        };
+        let fields = line.split('\t').collect::<Vec<_>>();
+        if fields.len() != 11 {
+            return Err(err(format!("expected 11 fields, got {}", fields.len())));
+        }
+        fn num<T: std::str::FromStr>(field: &str) -> Option<T> {
+            field.parse::<T>().ok()
+        }
+        let opt = |i: usize| -> Result<Option<f64>, HistoryError> {
+            if fields[i] == "-" {
+                Ok(None)
+            } else {
+                num(fields[i])
+                    .map(Some)
+                    .ok_or_else(|| err(format!("\"{}\" is not a number", fields[i])))
+            }
+        };
+        let req = |i: usize| -> Result<f64, HistoryError> {
+            num(fields[i]).ok_or_else(|| err(format!("\"{}\" is not a number", fields[i])))
+        };
+        let not_whole = |i: usize| err(format!("\"{}\" is not a whole number", fields[i]));
+        Ok(SessionSummary {
+            date: num(fields[0]).ok_or_else(|| not_whole(0))?,
+            mode: fields[1].to_string(),
+            target_bpm: opt(2)?,
+            duration: req(3)?,
+            taps: num(fields[4]).ok_or_else(|| not_whole(4))?,
+            max_combo: num(fields[5]).ok_or_else(|| not_whole(5))?,
+            score: num(fields[6]).ok_or_else(|| not_whole(6))?,
+            avg_bpm: opt(7)?,
+            peak_bpm: opt(8)?,
+            unstable_rate: opt(9)?,
+            accuracy: opt(10)?,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Record {
+    Score,
+    MaxCombo,
+    PeakBpm,
+    UnstableRate,
+    Accuracy,
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Record::Score => write!(f, "score"),
+            Record::MaxCombo => write!(f, "max combo"),
+            Record::PeakBpm => write!(f, "peak BPM"),
+            Record::UnstableRate => write!(f, "UR"),
+            Record::Accuracy => write!(f, "accuracy"),
+        }
+    }
+}
+
+/// The best of each value over a set of sessions.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PersonalBest {
+    pub score: u64,
+    pub max_combo: u32,
+    pub peak_bpm: Option<f64>,
+    /// Lower is better.
+    pub unstable_rate: Option<f64>,
+    pub accuracy: Option<f64>,
+}
+
+fn max_opt(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn min_opt(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl PersonalBest {
+    pub fn add(&mut self, summary: &SessionSummary) {
+        self.score = self.score.max(summary.score);
+        self.max_combo = self.max_combo.max(summary.max_combo);
+        self.peak_bpm = max_opt(self.peak_bpm, summary.peak_bpm);
+        self.unstable_rate = min_opt(self.unstable_rate, summary.unstable_rate);
+        self.accuracy = max_opt(self.accuracy, summary.accuracy);
+    }
+
+    /// Which bests the session improved on.
+    pub fn beaten_by(&self, summary: &SessionSummary) -> Vec<Record> {
+        let better = |new: Option<f64>, old: Option<f64>, higher: bool| match (new, old) {
+            (Some(new), Some(old)) => (higher && new > old) || (!higher && new < old),
+            (Some(_), None) => true,
+            _ => false,
+        };
+        let mut records = Vec::new();
+        if summary.score > self.score {
+            records.push(Record::Score);
+        }
+        if summary.max_combo > self.max_combo {
+            records.push(Record::MaxCombo);
+        }
+        if better(summary.peak_bpm, self.peak_bpm, true) {
+            records.push(Record::PeakBpm);
+        }
+        if better(summary.unstable_rate, self.unstable_rate, false) {
+            records.push(Record::UnstableRate);
+        }
+        if better(summary.accuracy, self.accuracy, true) {
+            records.push(Record::Accuracy);
+        }
+        records
+    }
+}
+
+/// Every finished session, stored as one tab separated line each.
+#[derive(Debug, Default)]
+pub struct History {
+    pub path: PathBuf,
+    pub sessions: Vec<SessionSummary>,
+    /// Lines that were left out because they couldn't be read, such as one cut short by a crash.
+    pub skipped: Vec<HistoryError>,
+}
+
+impl History {
+    /// Where the history is kept, in the data directory.
+    pub fn default_path() -> Result<PathBuf, HistoryError> {
+        Ok(data_dir().ok_or(HistoryError::NoDataDir)?.join("history.tsv"))
+    }
+
+    /// The history in the data directory. Empty if there is none yet.
+    pub fn load_default() -> Result<Self, HistoryError> {
+        History::load(&History::default_path()?)
+    }
+
+    /// Fails only when the file can't be read, invalid lines are skipped.
+    pub fn load(path: &Path) -> Result<Self, HistoryError> {
+        let mut history = History {
+            path: path.to_path_buf(),
+            ..Default::default()
+        };
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(history),
+            Err(e) => return Err(e.into()),
+        };
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match SessionSummary::from_line(line, i + 1) {
+                Ok(summary) => history.sessions.push(summary),
+                Err(e) => history.skipped.push(e),
+            }
+        }
+        Ok(history)
+    }
+
+    /// Adds the session and appends it to the file.
+    pub fn append(&mut self, summary: SessionSummary) -> Result<(), HistoryError> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().read(true).create(true).append(true).open(&self.path)?;
+        let len = file.metadata()?.len();
+        if len == 0 {
+            writeln!(file, "{}", HEADER)?;
+        } else {
+            // Starts on a line of its own after a line that was cut short.
+            let mut last = [0];
+            file.seek(SeekFrom::Start(len - 1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                writeln!(file)?;
+            }
+        }
+        writeln!(file, "{}", summary.to_line())?;
+        self.sessions.push(summary);
+        Ok(())
+    }
+
+    /// Bests of the sessions of a mode in a BPM bucket, `None` if there are no such sessions.
+    pub fn best(&self, mode: &str, bpm_bucket: Option<u32>) -> Option<PersonalBest> {
+        let mut sessions = self
+            .sessions
+            .iter()
+            .filter(|s| s.mode == mode && s.bpm_bucket() == bpm_bucket)
+            .peekable();
+        sessions.peek()?;
+        let mut best = PersonalBest::default();
+        for session in sessions {
+            best.add(session);
+        }
+        Some(best)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(score: u64, unstable_rate: Option<f64>) -> SessionSummary {
+        SessionSummary {
+            date: 1_600_000_000,
+            mode: "metronome".to_string(),
+            target_bpm: Some(180.0),
+            duration: 30.5,
+            taps: 366,
+            max_combo: 120,
+            score,
+            avg_bpm: Some(179.25),
+            peak_bpm: None,
+            unstable_rate,
+            accuracy: Some(0.9625),
+        }
+    }
+
+    #[test]
+    fn line_round_trip() {
+        let summary = summary(123_456, None);
+        assert_eq!(SessionSummary::from_line(&summary.to_line(), 1).unwrap(), summary);
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let path = std::env::temp_dir().join(format!("osu_practice_history_{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut history = History::load(&path).unwrap();
+        assert!(history.sessions.is_empty());
+        history.append(summary(1, Some(150.0))).unwrap();
+        history.append(summary(2, Some(120.0))).unwrap();
+        let loaded = History::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.sessions, history.sessions);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let line = summary(1, None).to_line();
+        match SessionSummary::from_line(&line.replacen("\t", " ", 1), 7) {
+            Err(HistoryError::Parse { line, message }) => {
+                assert_eq!(line, 7);
+                assert_eq!(message, "expected 11 fields, got 10");
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        let bad_score = line.replace("\t1\t", "\tlots\t");
+        assert!(SessionSummary::from_line(&bad_score, 1).is_err());
+        let bad_target = line.replace("\t180\t", "\tfast\t");
+        assert!(SessionSummary::from_line(&bad_target, 1).is_err());
+        assert!(SessionSummary::from_line("", 1).is_err());
+    }
+
+    #[test]
+    fn invalid_numbers() {
+        let line = summary(1, None).to_line();
+        for (field, value) in &[(0, "1.5"), (0, "-3"), (4, "366.0"), (5, "-1"), (5, "1e3"), (6, "99999999999999999999")] {
+            let mut fields = line.split('\t').collect::<Vec<_>>();
+            fields[*field] = value;
+            match SessionSummary::from_line(&fields.join("\t"), 1) {
+                Err(HistoryError::Parse { message, .. }) => {
+                    assert_eq!(message, format!("\"{}\" is not a whole number", value))
+                }
+                other => panic!("expected {} to be rejected, got {:?}", value, other),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_lines_are_skipped() {
+        let path = std::env::temp_dir().join(format!("osu_practice_history_skipped_{}", std::process::id()));
+        let good = summary(1, None).to_line();
+        // The last line was cut short while it was written.
+        let text = format!("{}\n{}\nnonsense\n{}", HEADER, good, &good[..24]);
+        std::fs::write(&path, text).unwrap();
+        let mut history = History::load(&path).unwrap();
+        assert_eq!(history.sessions, vec![summary(1, None)]);
+        assert_eq!(
+            history.skipped.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec![
+                "Invalid history at line 3: expected 11 fields, got 1".to_string(),
+                "Invalid history at line 4: expected 11 fields, got 3".to_string(),
+            ]
+        );
+
+        history.append(summary(2, None)).unwrap();
+        let loaded = History::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.sessions, vec![summary(1, None), summary(2, None)]);
+        assert_eq!(loaded.skipped.len(), 2);
+    }
+
+    #[test]
+    fn personal_bests() {
+        let history = History {
+            sessions: vec![summary(100, Some(150.0)), summary(300, Some(180.0)), summary(200, None)],
+            ..Default::default()
+        };
+        assert_eq!(history.best("stamina", Some(180)), None);
+        let best = history.best("metronome", Some(180)).unwrap();
+        assert_eq!(best.score, 300);
+        assert_eq!(best.unstable_rate, Some(150.0));
+        assert_eq!(best.peak_bpm, None);
+
+        assert_eq!(best.beaten_by(&summary(250, Some(160.0))), vec![]);
+        let mut better = summary(400, Some(140.0));
+        better.peak_bpm = Some(190.0);
+        assert_eq!(
+            best.beaten_by(&better),
+            vec![Record::Score, Record::PeakBpm, Record::UnstableRate]
+        );
+    }
+
+    #[test]
+    fn bpm_buckets() {
+        let mut summary = summary(0, None);
+        assert_eq!(summary.bpm_bucket(), Some(180));
+        summary.target_bpm = None;
+        assert_eq!(summary.bpm_bucket(), Some(170));
+        summary.avg_bpm = None;
+        assert_eq!(summary.bpm_bucket(), None);
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+    }
+}
@@ -0,0 +1,553 @@
+use easycurses::Input;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Instant;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Key {
+    Left,
+    Right,
+    Extra,
+}
+
+impl Key {
+    pub fn name(self) -> &'static str {
+        match self {
+            Key::Left => "left",
+            Key::Right => "right",
+            Key::Extra => "extra",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "left" => Some(Key::Left),
+            "right" => Some(Key::Right),
+            "extra" => Some(Key::Extra),
+            _ => None,
+        }
+    }
+}
+
+/// Which keys a stream may be played with.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Technique {
+    Any,
+    /// Never the same key twice in a row, except after a break.
+    Alternate,
+    /// Only the key the session was started with.
+    Singletap,
+}
+
+impl Default for Technique {
+    fn default() -> Self {
+        Technique::Any
+    }
+}
+
+impl Technique {
+    pub const ALL: [Technique; 3] = [Technique::Any, Technique::Alternate, Technique::Singletap];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Technique::Any => "any",
+            Technique::Alternate => "alternate",
+            Technique::Singletap => "singletap",
+        }
+    }
+
+    /// Whether `key` may be pressed after `first` started the session and `previous` was the last key
+    /// of the current stream.
+    pub fn allows(self, key: Key, first: Option<Key>, previous: Option<Key>) -> bool {
+        match self {
+            Technique::Any => true,
+            Technique::Alternate => previous != Some(key),
+            Technique::Singletap => first.map(|first| first == key).unwrap_or(true),
+        }
+    }
+}
+
+impl FromStr for Technique {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Technique::ALL
+            .iter()
+            .copied()
+            .find(|t| t.name() == s)
+            .ok_or_else(|| format!("Unknown technique \"{}\", expected one of any, alternate, singletap", s))
+    }
+}
+
+impl fmt::Display for Technique {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Technique::Any => write!(f, "any keys"),
+            Technique::Alternate => write!(f, "full alternate"),
+            Technique::Singletap => write!(f, "singletap"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum InputEvent {
+    /// A key press and the time it was read at.
+    Input(Key, Instant),
+    /// A key release, from the input backends that see them.
+    Release(Key, Instant),
+    /// Any key or button, bound or not, for the menus.
+    Press(Binding),
+    Pause,
+    Reset,
+    Quit,
+}
+
+/// What a binding does.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Slot {
+    Tap(Key),
+    Pause,
+    Reset,
+}
+
+impl Slot {
+    pub const ALL: [Slot; 5] = [
+        Slot::Tap(Key::Left),
+        Slot::Tap(Key::Right),
+        Slot::Tap(Key::Extra),
+        Slot::Pause,
+        Slot::Reset,
+    ];
+
+    /// Name of the slot in `input.yml`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Slot::Tap(key) => key.name(),
+            Slot::Pause => "pause",
+            Slot::Reset => "reset",
+        }
+    }
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Slot::Tap(key) => write!(f, "{} tap", key.name()),
+            slot => write!(f, "{}", slot.name()),
+        }
+    }
+}
+
+/// Something that can be bound to a key slot.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Binding {
+    Input(Input),
+    /// Mouse button 1 (left), 2 (middle) or 3 (right).
+    Mouse(u8),
+}
+
+const NAMED_INPUTS: [(&str, Input); 26] = [
+    ("space", Input::Character(' ')),
+    ("tab", Input::Character('\t')),
+    ("enter", Input::Character('\n')),
+    ("f1", Input::KeyF1),
+    ("f2", Input::KeyF2),
+    ("f3", Input::KeyF3),
+    ("f4", Input::KeyF4),
+    ("f5", Input::KeyF5),
+    ("f6", Input::KeyF6),
+    ("f7", Input::KeyF7),
+    ("f8", Input::KeyF8),
+    ("f9", Input::KeyF9),
+    ("f10", Input::KeyF10),
+    ("f11", Input::KeyF11),
+    ("f12", Input::KeyF12),
+    ("keypad_7", Input::KeyA1),
+    ("keypad_9", Input::KeyA3),
+    ("keypad_5", Input::KeyB2),
+    ("keypad_1", Input::KeyC1),
+    ("keypad_3", Input::KeyC3),
+    ("keypad_enter", Input::KeyEnter),
+    ("arrow_up", Input::KeyUp),
+    ("arrow_down", Input::KeyDown),
+    ("arrow_left", Input::KeyLeft),
+    ("arrow_right", Input::KeyRight),
+    ("backspace", Input::KeyBackspace),
+];
+
+const MOUSE_BUTTONS: [(&str, u8); 3] = [("mouse_left", 1), ("mouse_middle", 2), ("mouse_right", 3)];
+
+impl Binding {
+    /// Parses a name as written in `input.yml`: a single character, or one of the named keys.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some((_, input)) = NAMED_INPUTS.iter().find(|(n, _)| *n == name) {
+            return Some(Binding::Input(*input));
+        }
+        if let Some((_, button)) = MOUSE_BUTTONS.iter().find(|(n, _)| *n == name) {
+            return Some(Binding::Mouse(*button));
+        }
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            // Escape is reserved for quitting.
+            (Some(c), None) if !c.is_control() => Some(Binding::Input(Input::Character(c))),
+            _ => None,
+        }
+    }
+
+    /// The name `from_name` accepts, if there is one.
+    pub fn name(&self) -> Option<String> {
+        match self {
+            Binding::Input(input) => NAMED_INPUTS
+                .iter()
+                .find(|(_, i)| i == input)
+                .map(|(n, _)| n.to_string())
+                .or_else(|| match input {
+                    Input::Character(c) if !c.is_control() => Some(c.to_string()),
+                    _ => None,
+                }),
+            Binding::Mouse(button) => MOUSE_BUTTONS
+                .iter()
+                .find(|(_, b)| b == button)
+                .map(|(n, _)| n.to_string()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum KeymapError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+    UnknownKey { slot: Slot, name: String },
+    Duplicate { name: String, first: Slot, second: Slot },
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeymapError::Io(e) => write!(f, "Failed to access key bindings: {}", e),
+            KeymapError::Yaml(e) => write!(f, "Invalid key bindings: {}", e),
+            KeymapError::UnknownKey { slot, name } => {
+                write!(f, "Unknown key \"{}\" bound to {}", name, slot)
+            }
+            KeymapError::Duplicate { name, first, second } if first == second => {
+                write!(f, "Key \"{}\" is listed twice for {}", name, first)
+            }
+            KeymapError::Duplicate { name, first, second } => {
+                write!(f, "Key \"{}\" is bound to both {} and {}", name, first, second)
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeymapError {}
+
+impl From<std::io::Error> for KeymapError {
+    fn from(e: std::io::Error) -> Self {
+        KeymapError::Io(e)
+    }
+}
+
+impl From<serde_yaml::Error> for KeymapError {
+    fn from(e: serde_yaml::Error) -> Self {
+        KeymapError::Yaml(e)
+    }
+}
+
+// Written at the top of `input.yml` when saving, since serde_yaml drops comments.
+const CONFIG_HELP: &str = "\
+# Keys bound to each slot.
+# A key is either a single character (\"x\", \"z\", \";\"), or one of:
+#   space, tab, enter, backspace, f1 to f12,
+#   keypad_1, keypad_3, keypad_5, keypad_7, keypad_9, keypad_enter,
+#   arrow_up, arrow_down, arrow_left, arrow_right,
+#   mouse_left, mouse_middle, mouse_right.
+# Escape quits and can't be bound.
+# Run `osu_practice keys` to change them from the terminal.
+";
+
+/// The contents of `input.yml`: key names for every slot.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeymapConfig {
+    pub left: Vec<String>,
+    pub right: Vec<String>,
+    pub extra: Vec<String>,
+    pub pause: Vec<String>,
+    pub reset: Vec<String>,
+}
+
+impl KeymapConfig {
+    fn slot(&self, slot: Slot) -> &Vec<String> {
+        match slot {
+            Slot::Tap(Key::Left) => &self.left,
+            Slot::Tap(Key::Right) => &self.right,
+            Slot::Tap(Key::Extra) => &self.extra,
+            Slot::Pause => &self.pause,
+            Slot::Reset => &self.reset,
+        }
+    }
+
+    fn slot_mut(&mut self, slot: Slot) -> &mut Vec<String> {
+        match slot {
+            Slot::Tap(Key::Left) => &mut self.left,
+            Slot::Tap(Key::Right) => &mut self.right,
+            Slot::Tap(Key::Extra) => &mut self.extra,
+            Slot::Pause => &mut self.pause,
+            Slot::Reset => &mut self.reset,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Keymap {
+    pub map: HashMap<Binding, Slot>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap {
+            map: [
+                (Binding::Input(Input::Character('x')), Slot::Tap(Key::Left)),
+                (Binding::Input(Input::Character('b')), Slot::Tap(Key::Right)),
+                (Binding::Input(Input::Character('p')), Slot::Pause),
+                (Binding::Input(Input::Character('r')), Slot::Reset),
+            ].iter().cloned().collect(),
+        }
+    }
+}
+
+impl Keymap {
+    /// Loads the bindings of a file, or the defaults if it doesn't bind anything.
+    pub fn load(path: &Path) -> Result<Self, KeymapError> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Keymap::default()),
+            Err(e) => return Err(e.into()),
+        };
+        // An empty document isn't a valid mapping for serde_yaml.
+        let content = text
+            .lines()
+            .filter(|l| !l.trim_start().starts_with('#'))
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && *l != "---")
+            .count();
+        if content == 0 {
+            return Ok(Keymap::default());
+        }
+        let config = serde_yaml::from_str::<KeymapConfig>(&text)?;
+        if config == KeymapConfig::default() {
+            return Ok(Keymap::default());
+        }
+        Keymap::from_config(&config)
+    }
+
+    pub fn from_config(config: &KeymapConfig) -> Result<Self, KeymapError> {
+        let mut map = HashMap::new();
+        for slot in Slot::ALL.iter() {
+            for name in config.slot(*slot).iter() {
+                let binding = Binding::from_name(name).ok_or_else(|| KeymapError::UnknownKey {
+                    slot: *slot,
+                    name: name.clone(),
+                })?;
+                if let Some(first) = map.insert(binding, *slot) {
+                    return Err(KeymapError::Duplicate {
+                        name: name.clone(),
+                        first,
+                        second: *slot,
+                    });
+                }
+            }
+        }
+        Ok(Keymap { map })
+    }
+
+    pub fn to_config(&self) -> KeymapConfig {
+        let mut config = KeymapConfig::default();
+        for slot in Slot::ALL.iter() {
+            *config.slot_mut(*slot) = self.names(*slot);
+        }
+        config
+    }
+
+    /// Writes the bindings to a file in the format `load` reads.
+    pub fn save(&self, path: &Path) -> Result<(), KeymapError> {
+        let yaml = serde_yaml::to_string(&self.to_config())?;
+        std::fs::write(path, format!("{}{}\n", CONFIG_HELP, yaml.trim_end()))?;
+        Ok(())
+    }
+
+    /// Makes `binding` the only key of `slot`.
+    /// Fails without changing anything if it's already bound to another slot.
+    pub fn bind(&mut self, slot: Slot, binding: Binding) -> Result<(), KeymapError> {
+        let name = binding.name().ok_or_else(|| KeymapError::UnknownKey {
+            slot,
+            name: format!("{:?}", binding),
+        })?;
+        match self.map.get(&binding) {
+            Some(first) if *first != slot => {
+                return Err(KeymapError::Duplicate {
+                    name,
+                    first: *first,
+                    second: slot,
+                })
+            }
+            _ => {}
+        }
+        self.clear(slot);
+        self.map.insert(binding, slot);
+        Ok(())
+    }
+
+    pub fn clear(&mut self, slot: Slot) {
+        self.map.retain(|_, s| *s != slot);
+    }
+
+    pub fn has_mouse_bindings(&self) -> bool {
+        self.map.keys().any(|b| matches!(b, Binding::Mouse(_)))
+    }
+
+    /// Names of the keys bound to a slot.
+    pub fn names(&self, slot: Slot) -> Vec<String> {
+        let mut names = self
+            .map
+            .iter()
+            .filter(|(_, s)| **s == slot)
+            .filter_map(|(b, _)| b.name())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(left: &[&str], right: &[&str]) -> KeymapConfig {
+        KeymapConfig {
+            left: left.iter().map(|n| n.to_string()).collect(),
+            right: right.iter().map(|n| n.to_string()).collect(),
+            pause: vec!["space".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn binding_names() {
+        assert_eq!(Binding::from_name("z"), Some(Binding::Input(Input::Character('z'))));
+        assert_eq!(Binding::from_name("keypad_enter"), Some(Binding::Input(Input::KeyEnter)));
+        assert_eq!(Binding::from_name("mouse_right"), Some(Binding::Mouse(3)));
+        for name in &["", "zx", "escape", "\u{1b}", "mouse_4", "ctrl+x"] {
+            assert_eq!(Binding::from_name(name), None, "{:?}", name);
+        }
+        for name in &["z", ";", "space", "f12", "arrow_up", "mouse_middle"] {
+            assert_eq!(Binding::from_name(name).and_then(|b| b.name()).as_deref(), Some(*name));
+        }
+    }
+
+    #[test]
+    fn from_config() {
+        let keymap = Keymap::from_config(&config(&["z", "mouse_left"], &["x"])).unwrap();
+        assert_eq!(keymap.map.len(), 4);
+        assert_eq!(keymap.map.get(&Binding::Mouse(1)), Some(&Slot::Tap(Key::Left)));
+        assert_eq!(keymap.map.get(&Binding::Input(Input::Character(' '))), Some(&Slot::Pause));
+        assert!(keymap.has_mouse_bindings());
+        assert_eq!(keymap.names(Slot::Tap(Key::Left)), vec!["mouse_left", "z"]);
+    }
+
+    #[test]
+    fn unknown_key_names() {
+        match Keymap::from_config(&config(&["z"], &["escape"])) {
+            Err(KeymapError::UnknownKey { slot, name }) => {
+                assert_eq!(slot, Slot::Tap(Key::Right));
+                assert_eq!(name, "escape");
+            }
+            other => panic!("expected an unknown key, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn duplicate_bindings() {
+        match Keymap::from_config(&config(&["z"], &["x", "z"])) {
+            Err(KeymapError::Duplicate { name, first, second }) => {
+                assert_eq!(name, "z");
+                assert_eq!(first, Slot::Tap(Key::Left));
+                assert_eq!(second, Slot::Tap(Key::Right));
+            }
+            other => panic!("expected a duplicate, got {:?}", other),
+        }
+        let error = Keymap::from_config(&config(&["z", "z"], &["x"])).unwrap_err();
+        assert_eq!(error.to_string(), "Key \"z\" is listed twice for left tap");
+    }
+
+    #[test]
+    fn bind_replaces_the_slot() {
+        let mut keymap = Keymap::default();
+        keymap.bind(Slot::Tap(Key::Left), Binding::Input(Input::Character('z'))).unwrap();
+        assert_eq!(keymap.names(Slot::Tap(Key::Left)), vec!["z"]);
+        let taken = keymap.bind(Slot::Tap(Key::Left), Binding::Input(Input::Character('b')));
+        assert!(taken.is_err());
+        assert_eq!(keymap.names(Slot::Tap(Key::Left)), vec!["z"]);
+        keymap.clear(Slot::Reset);
+        assert!(keymap.names(Slot::Reset).is_empty());
+    }
+
+    #[test]
+    fn save_and_reload() {
+        let path = std::env::temp_dir().join(format!("osu_practice_input_{}.yml", std::process::id()));
+        let keymap = Keymap::from_config(&config(&["z", "mouse_left"], &["x", "arrow_right"])).unwrap();
+        keymap.save(&path).unwrap();
+        let loaded = Keymap::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), keymap);
+    }
+
+    #[test]
+    fn missing_or_empty_file_uses_the_defaults() {
+        let path = std::env::temp_dir().join(format!("osu_practice_empty_{}.yml", std::process::id()));
+        assert_eq!(Keymap::load(&path).unwrap(), Keymap::default());
+        std::fs::write(&path, CONFIG_HELP).unwrap();
+        let loaded = Keymap::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), Keymap::default());
+    }
+
+    #[test]
+    fn techniques() {
+        use Key::{Left as L, Right as R};
+        // Technique, key pressed, first key of the session, previous key of the stream, and whether it's allowed.
+        let table = [
+            (Technique::Any, L, None, None, true),
+            (Technique::Any, L, Some(L), Some(L), true),
+            (Technique::Any, R, Some(L), Some(R), true),
+            (Technique::Alternate, L, None, None, true),
+            (Technique::Alternate, R, Some(L), Some(L), true),
+            (Technique::Alternate, L, Some(L), Some(R), true),
+            (Technique::Alternate, L, Some(L), Some(L), false),
+            (Technique::Alternate, R, Some(L), Some(R), false),
+            // After a break.
+            (Technique::Alternate, R, Some(R), None, true),
+            (Technique::Singletap, L, None, None, true),
+            (Technique::Singletap, R, None, None, true),
+            (Technique::Singletap, L, Some(L), Some(L), true),
+            (Technique::Singletap, L, Some(L), None, true),
+            (Technique::Singletap, R, Some(L), Some(L), false),
+            (Technique::Singletap, R, Some(L), None, false),
+            (Technique::Singletap, L, Some(R), Some(L), false),
+        ];
+        for (technique, key, first, previous, allowed) in &table {
+            assert_eq!(
+                technique.allows(*key, *first, *previous),
+                *allowed,
+                "{} {:?} after {:?}, {:?} first",
+                technique,
+                key,
+                previous,
+                first
+            );
+        }
+    }
+}
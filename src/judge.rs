@@ -0,0 +1,473 @@
+use crate::beatmap::Chart;
+use crate::rounds::Rounds;
+use crate::stats::Divisor;
+use crate::step::Ramp;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Judgement {
+    Great,
+    Good,
+    Meh,
+    Miss,
+}
+
+impl Judgement {
+    pub fn score(self) -> u32 {
+        match self {
+            Judgement::Great => 300,
+            Judgement::Good => 100,
+            Judgement::Meh => 50,
+            Judgement::Miss => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for Judgement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Judgement::Miss => write!(f, "Miss"),
+            j => write!(f, "{}", j.score()),
+        }
+    }
+}
+
+/// Largest distance in milliseconds from a beat for each judgement, in either direction.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HitWindows {
+    pub great: f64,
+    pub good: f64,
+    pub meh: f64,
+}
+
+impl HitWindows {
+    /// Same windows as osu!standard circles for the given Overall Difficulty.
+    pub fn from_od(od: f64) -> Self {
+        HitWindows {
+            great: 80.0 - 6.0 * od,
+            good: 140.0 - 8.0 * od,
+            meh: 200.0 - 10.0 * od,
+        }
+    }
+
+    pub fn judge(&self, offset: f64) -> Judgement {
+        let offset = offset.abs();
+        if offset <= self.great {
+            Judgement::Great
+        } else if offset <= self.good {
+            Judgement::Good
+        } else if offset <= self.meh {
+            Judgement::Meh
+        } else {
+            Judgement::Miss
+        }
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Judgements {
+    pub great: u32,
+    pub good: u32,
+    pub meh: u32,
+    pub miss: u32,
+}
+
+impl Judgements {
+    pub fn add(&mut self, judgement: Judgement) {
+        match judgement {
+            Judgement::Great => self.great += 1,
+            Judgement::Good => self.good += 1,
+            Judgement::Meh => self.meh += 1,
+            Judgement::Miss => self.miss += 1,
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.great + self.good + self.meh + self.miss
+    }
+
+    /// osu! accuracy, from 0.0 to 1.0.
+    pub fn accuracy(&self) -> Option<f64> {
+        if self.total() == 0 {
+            return None;
+        }
+        let points = self.great * 300 + self.good * 100 + self.meh * 50;
+        Some(points as f64 / (self.total() * 300) as f64)
+    }
+}
+
+/// A tap judged against the metronome.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hit {
+    pub judgement: Judgement,
+    /// Milliseconds from the beat, negative when early.
+    pub offset: f64,
+    /// Beats that went by without being hit since the previous hit.
+    pub skipped: u32,
+}
+
+/// Where the expected beats come from.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    /// A steady stream at the given beatmap BPM and snap.
+    Fixed { bpm: f64, divisor: Divisor },
+    /// The notes of a beatmap.
+    Chart(Chart),
+    /// A stream that keeps speeding up, for the step test.
+    Ramp(Ramp),
+    /// Streams with rests in between, for interval training.
+    Rounds(Rounds),
+}
+
+impl Pattern {
+    /// Milliseconds from the first beat to the given beat, if the pattern goes that far.
+    pub fn beat_time(&self, beat: usize) -> Option<f64> {
+        match self {
+            Pattern::Fixed { bpm, divisor } => Some(beat as f64 * divisor.interval(*bpm) * 1000.0),
+            Pattern::Chart(chart) => chart.notes.get(beat).copied(),
+            Pattern::Ramp(ramp) => Some(ramp.beat_time(beat)),
+            Pattern::Rounds(rounds) => rounds.beat_time(beat),
+        }
+    }
+
+    /// Number of beats, or `None` if the pattern never ends.
+    pub fn len(&self) -> Option<usize> {
+        match self {
+            Pattern::Fixed { .. } | Pattern::Ramp(_) => None,
+            Pattern::Chart(chart) => Some(chart.notes.len()),
+            Pattern::Rounds(rounds) => Some(rounds.len()),
+        }
+    }
+
+    /// Beatmap BPM at the given number of milliseconds from the first beat.
+    pub fn bpm_at(&self, time: f64) -> Option<f64> {
+        match self {
+            Pattern::Fixed { bpm, .. } => Some(*bpm),
+            Pattern::Chart(chart) => chart.bpm_at(time),
+            Pattern::Ramp(ramp) => Some(ramp.bpm_at(time)),
+            Pattern::Rounds(rounds) => Some(rounds.bpm),
+        }
+    }
+}
+
+/// Expected beats, anchored on the first tap.
+#[derive(Clone, Debug)]
+pub struct Metronome {
+    pub pattern: Pattern,
+    pub od: f64,
+    /// Start over right after the last beat instead of waiting for a break.
+    pub looping: bool,
+    pub windows: HitWindows,
+    start: Option<Instant>,
+    next_beat: usize,
+}
+
+impl Metronome {
+    pub fn new(pattern: Pattern, od: f64) -> Self {
+        Metronome {
+            pattern,
+            od,
+            looping: false,
+            windows: HitWindows::from_od(od),
+            start: None,
+            next_beat: 0,
+        }
+    }
+
+    pub fn fixed(bpm: f64, divisor: Divisor, od: f64) -> Self {
+        Metronome::new(Pattern::Fixed { bpm, divisor }, od)
+    }
+
+    pub fn start(&self) -> Option<Instant> {
+        self.start
+    }
+
+    /// Forgets the current anchor so the next tap starts the pattern over.
+    pub fn restart(&mut self) {
+        self.start = None;
+        self.next_beat = 0;
+    }
+
+    /// Pushes the upcoming beats back, to make up for a pause.
+    pub fn delay(&mut self, by: Duration) {
+        if let Some(start) = self.start.as_mut() {
+            *start += by;
+        }
+    }
+
+    /// Index of the first beat that hasn't been hit or skipped yet.
+    pub fn next_beat(&self) -> usize {
+        self.next_beat
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.pattern
+            .len()
+            .map(|len| self.next_beat >= len)
+            .unwrap_or(false)
+    }
+
+    /// Milliseconds since the first beat.
+    pub fn elapsed(&self, time: Instant) -> Option<f64> {
+        self.start
+            .map(|start| time.duration_since(start).as_secs_f64() * 1000.0)
+    }
+
+    /// Judges a tap against the closest beat that wasn't hit yet.
+    /// Returns `None` once there are no beats left.
+    pub fn judge(&mut self, time: Instant) -> Option<Hit> {
+        let elapsed = match self.elapsed(time) {
+            Some(elapsed) => elapsed,
+            None => {
+                self.pattern.beat_time(0)?;
+                self.start = Some(time);
+                self.next_beat = 1;
+                return Some(Hit {
+                    judgement: Judgement::Great,
+                    offset: 0.0,
+                    skipped: 0,
+                });
+            }
+        };
+        // Every beat can only be hit once.
+        let mut beat = self.next_beat;
+        let mut beat_time = self.pattern.beat_time(beat)?;
+        while let Some(next_time) = self.pattern.beat_time(beat + 1) {
+            if (elapsed - next_time).abs() < (elapsed - beat_time).abs() {
+                beat += 1;
+                beat_time = next_time;
+            } else {
+                break;
+            }
+        }
+        let offset = elapsed - beat_time;
+        let judgement = self.windows.judge(offset);
+        if judgement == Judgement::Miss {
+            return Some(Hit {
+                judgement,
+                offset,
+                skipped: 0,
+            });
+        }
+        let skipped = (beat - self.next_beat) as u32;
+        self.next_beat = beat + 1;
+        Some(Hit {
+            judgement,
+            offset,
+            skipped,
+        })
+    }
+}
+
+/// Recent signed hit errors, drawn as an osu!-style hit error meter.
+#[derive(Clone, Debug)]
+pub struct HitErrors {
+    pub windows: HitWindows,
+    errors: VecDeque<(Instant, f64)>,
+}
+
+impl HitErrors {
+    /// Seconds before a tick fades out completely.
+    pub const LIFETIME: f64 = 3.0;
+    const MAX_TICKS: usize = 32;
+
+    pub fn new(windows: HitWindows) -> Self {
+        HitErrors {
+            windows,
+            errors: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, time: Instant, offset: f64) {
+        self.errors.push_back((time, offset));
+        while self.errors.len() > Self::MAX_TICKS {
+            self.errors.pop_front();
+        }
+    }
+
+    /// Age in seconds and offset in milliseconds of the ticks that haven't faded out yet.
+    pub fn recent(&self, now: Instant) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.errors
+            .iter()
+            .map(move |(time, offset)| (now.duration_since(*time).as_secs_f64(), *offset))
+            .filter(|(age, _)| *age < Self::LIFETIME)
+    }
+
+    /// Mean offset of the visible ticks.
+    pub fn mean(&self, now: Instant) -> Option<f64> {
+        let (count, sum) = self
+            .recent(now)
+            .fold((0, 0.0), |(count, sum), (_, offset)| (count + 1, sum + offset));
+        if count == 0 {
+            None
+        } else {
+            Some(sum / count as f64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    // Offsets to the nearest millisecond, the taps are all on whole milliseconds.
+    fn judge(metronome: &mut Metronome, time: Instant) -> Option<(Judgement, f64, u32)> {
+        metronome
+            .judge(time)
+            .map(|hit| (hit.judgement, hit.offset.round(), hit.skipped))
+    }
+
+    #[test]
+    fn windows() {
+        assert_eq!(
+            HitWindows::from_od(0.0),
+            HitWindows {
+                great: 80.0,
+                good: 140.0,
+                meh: 200.0
+            }
+        );
+        let windows = HitWindows::from_od(10.0);
+        assert_eq!((windows.great, windows.good, windows.meh), (20.0, 60.0, 100.0));
+        assert_eq!(windows.judge(0.0), Judgement::Great);
+        assert_eq!(windows.judge(-20.0), Judgement::Great);
+        assert_eq!(windows.judge(20.5), Judgement::Good);
+        assert_eq!(windows.judge(-60.0), Judgement::Good);
+        assert_eq!(windows.judge(100.0), Judgement::Meh);
+        assert_eq!(windows.judge(-100.5), Judgement::Miss);
+    }
+
+    #[test]
+    fn accuracy() {
+        let mut judgements = Judgements::default();
+        assert_eq!(judgements.accuracy(), None);
+        judgements.add(Judgement::Great);
+        assert_eq!(judgements.accuracy(), Some(1.0));
+        for judgement in &[Judgement::Good, Judgement::Meh, Judgement::Miss] {
+            judgements.add(*judgement);
+        }
+        assert_eq!(judgements.total(), 4);
+        assert_eq!(judgements.accuracy(), Some((300.0 + 100.0 + 50.0) / 1200.0));
+    }
+
+    #[test]
+    fn first_tap_starts_the_pattern() {
+        // 100ms between beats.
+        let mut metronome = Metronome::fixed(150.0, Divisor::new(4).unwrap(), 8.0);
+        let start = Instant::now();
+        assert_eq!(judge(&mut metronome, start), Some((Judgement::Great, 0.0, 0)));
+        assert_eq!(metronome.start(), Some(start));
+        assert_eq!(metronome.next_beat(), 1);
+    }
+
+    #[test]
+    fn taps_go_to_the_nearest_beat() {
+        let mut metronome = Metronome::fixed(150.0, Divisor::new(4).unwrap(), 8.0);
+        let start = Instant::now();
+        metronome.judge(start);
+        assert_eq!(judge(&mut metronome, at(start, 110)), Some((Judgement::Great, 10.0, 0)));
+        assert_eq!(judge(&mut metronome, at(start, 190)), Some((Judgement::Great, -10.0, 0)));
+        // Closer to the beat that was just hit, but that one is taken.
+        assert_eq!(judge(&mut metronome, at(start, 250)), Some((Judgement::Good, -50.0, 0)));
+        assert_eq!(metronome.next_beat(), 4);
+    }
+
+    #[test]
+    fn misses_leave_the_beat_to_hit() {
+        // One beat a second, with a 120ms meh window.
+        let mut metronome = Metronome::fixed(60.0, Divisor::new(1).unwrap(), 8.0);
+        let start = Instant::now();
+        metronome.judge(start);
+        assert_eq!(judge(&mut metronome, at(start, 850)), Some((Judgement::Miss, -150.0, 0)));
+        assert_eq!(metronome.next_beat(), 1);
+        assert_eq!(judge(&mut metronome, at(start, 1100)), Some((Judgement::Meh, 100.0, 0)));
+        assert_eq!(metronome.next_beat(), 2);
+    }
+
+    #[test]
+    fn skipped_beats_are_counted() {
+        let mut metronome = Metronome::fixed(60.0, Divisor::new(1).unwrap(), 8.0);
+        let start = Instant::now();
+        metronome.judge(start);
+        assert_eq!(judge(&mut metronome, at(start, 3050)), Some((Judgement::Good, 50.0, 2)));
+        assert_eq!(metronome.next_beat(), 4);
+        assert_eq!(judge(&mut metronome, at(start, 4000)), Some((Judgement::Great, 0.0, 0)));
+    }
+
+    #[test]
+    fn charts_end() {
+        let chart = Chart {
+            name: "test".to_string(),
+            notes: vec![0.0, 100.0, 250.0],
+            bpms: vec![(0.0, 150.0)],
+        };
+        let mut metronome = Metronome::new(Pattern::Chart(chart), 8.0);
+        let start = Instant::now();
+        metronome.judge(start);
+        assert!(!metronome.is_finished());
+        assert_eq!(judge(&mut metronome, at(start, 240)), Some((Judgement::Great, -10.0, 1)));
+        assert!(metronome.is_finished());
+        assert_eq!(judge(&mut metronome, at(start, 350)), None);
+        metronome.restart();
+        assert_eq!(judge(&mut metronome, at(start, 400)), Some((Judgement::Great, 0.0, 0)));
+
+        let empty = Chart {
+            notes: Vec::new(),
+            ..Chart::default()
+        };
+        assert_eq!(Metronome::new(Pattern::Chart(empty), 8.0).judge(start), None);
+    }
+
+    #[test]
+    fn delays_push_the_beats_back() {
+        let mut metronome = Metronome::fixed(60.0, Divisor::new(1).unwrap(), 8.0);
+        let start = Instant::now();
+        metronome.judge(start);
+        metronome.delay(Duration::from_secs(5));
+        assert_eq!(judge(&mut metronome, at(start, 6000)), Some((Judgement::Great, 0.0, 0)));
+    }
+
+    #[test]
+    fn hit_error_mean() {
+        let mut errors = HitErrors::new(HitWindows::from_od(8.0));
+        let start = Instant::now();
+        assert_eq!(errors.mean(start), None);
+        for (i, offset) in [-12.0, 4.0, 20.0, -8.0].iter().enumerate() {
+            errors.push(at(start, i as u64 * 100), *offset);
+        }
+        assert_eq!(errors.mean(at(start, 300)), Some(1.0));
+        let ages = errors.recent(at(start, 300)).map(|(age, _)| age).collect::<Vec<_>>();
+        assert_eq!(ages.len(), 4);
+        assert!((ages[0] - 0.3).abs() < 1e-9 && ages[3] == 0.0);
+    }
+
+    #[test]
+    fn hit_errors_fade_out() {
+        let mut errors = HitErrors::new(HitWindows::from_od(8.0));
+        let start = Instant::now();
+        errors.push(start, -30.0);
+        errors.push(at(start, 1000), 10.0);
+        errors.push(at(start, 2000), 20.0);
+        assert_eq!(errors.mean(at(start, 2000)), Some(0.0));
+        // The first tick is gone once it is as old as the lifetime.
+        assert_eq!(errors.mean(at(start, 3000)), Some(15.0));
+        assert_eq!(errors.mean(at(start, 5000)), None);
+    }
+
+    #[test]
+    fn only_the_latest_hit_errors_are_kept() {
+        let mut errors = HitErrors::new(HitWindows::from_od(8.0));
+        let start = Instant::now();
+        errors.push(start, 1000.0);
+        for _ in 0..HitErrors::MAX_TICKS {
+            errors.push(start, 5.0);
+        }
+        assert_eq!(errors.recent(start).count(), HitErrors::MAX_TICKS);
+        assert_eq!(errors.mean(start), Some(5.0));
+    }
+}
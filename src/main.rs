@@ -3,24 +3,77 @@ use amethyst::ecs::*;
 use amethyst::prelude::*;
 use amethyst::shrev::{EventChannel, ReaderId};
 use amethyst::utils::*;
-use amethyst::utils::circular_buffer::*;
 use easycurses::*;
-use std::collections::HashMap;
+use std::io::Write as _;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::*;
 use lazy_static::lazy_static;
 
-pub struct Curses(pub EasyCurses);
+mod beatmap;
+mod burst;
+mod clock;
+mod drift;
+mod evdev;
+mod frame;
+mod history;
+mod input;
+mod judge;
+mod options;
+mod poller;
+mod rounds;
+mod session;
+mod stamina;
+mod states;
+mod stats;
+mod step;
+mod training;
 
-#[derive(Default)]
-pub struct Stats {
-    pub total: u32,
-    pub combo: u32,
-    pub score: u64,
+use beatmap::*;
+use clock::*;
+use drift::*;
+use evdev::*;
+use frame::*;
+use input::*;
+use judge::*;
+use options::*;
+use poller::*;
+use rounds::*;
+use session::*;
+use states::*;
+use stats::*;
+use step::*;
+
+/// The terminal, shared between the systems drawing it and the input thread.
+#[derive(Clone)]
+pub struct Curses(Arc<Mutex<EasyCurses>>);
+
+impl Curses {
+    pub fn new(curses: EasyCurses) -> Self {
+        Curses(Arc::new(Mutex::new(curses)))
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, EasyCurses> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
 }
 
-// boi
+/// Problems worth telling the user about, shown at the bottom of the screen.
+#[derive(Default)]
+pub struct Warnings(pub Vec<String>);
+
+/// When the session was paused, if it is.
+#[derive(Default)]
+pub struct Paused(pub Option<Instant>);
+
+const KEYMAP_PATH: &str = "resources/input.yml";
+
+const DEFAULT_OD: f64 = 8.0;
+
+// ncurses isn't thread safe, which is why `EasyCurses` is neither `Send` nor `Sync`. Once the terminal is shared,
+// every call into ncurses, including the pancurses functions called directly, is made while holding the mutex, so
+// only one thread is ever inside ncurses at a time.
 unsafe impl Send for Curses {}
-// Garanteed by the system execution scheduler
 unsafe impl Sync for Curses {}
 
 lazy_static! {
@@ -28,92 +81,626 @@ lazy_static! {
     static ref COLOR_EDGE: easycurses::ColorPair = easycurses::ColorPair::new(Color::Yellow, Color::Black);
     static ref COLOR_TITLE: easycurses::ColorPair = easycurses::ColorPair::new(Color::Red, Color::White);
     static ref COLOR_DEBUG: easycurses::ColorPair = easycurses::ColorPair::new(Color::Blue, Color::White);
+    static ref COLOR_GREAT: easycurses::ColorPair = easycurses::ColorPair::new(Color::Cyan, Color::Black);
+    static ref COLOR_GOOD: easycurses::ColorPair = easycurses::ColorPair::new(Color::Green, Color::Black);
+    static ref COLOR_MEH: easycurses::ColorPair = easycurses::ColorPair::new(Color::Yellow, Color::Black);
+    static ref COLOR_ERROR: easycurses::ColorPair = easycurses::ColorPair::new(Color::Red, Color::Black);
+}
+
+// Seconds the technique line stays highlighted after a wrong key.
+const TECHNIQUE_ERROR_FLASH: f64 = 1.0;
+
+// Columns on each side of the center of the hit error meter.
+const HIT_ERROR_HALF_WIDTH: i32 = 30;
+// Room for the "early" label.
+const HIT_ERROR_LEFT: i32 = 6;
+
+fn hit_error_column(offset: f64, windows: &HitWindows) -> i32 {
+    let col = (offset / windows.meh * HIT_ERROR_HALF_WIDTH as f64).round() as i32;
+    HIT_ERROR_LEFT + HIT_ERROR_HALF_WIDTH + col.max(-HIT_ERROR_HALF_WIDTH).min(HIT_ERROR_HALF_WIDTH)
+}
+
+// Milliseconds of chart covered by one column of the note lane.
+const LANE_MS_PER_COL: f64 = 20.0;
+const LANE_WIDTH: i32 = 60;
+
+/// Draws the upcoming notes of a chart scrolling towards the hit marker.
+fn draw_chart_lane(frame: &mut Frame, metronome: &Metronome, elapsed: f64, row: i32) {
+    frame.move_rc(row, 0);
+    frame.print("[");
+    let mut beat = metronome.next_beat();
+    while let Some(time) = metronome.pattern.beat_time(beat) {
+        let col = 1 + ((time - elapsed) / LANE_MS_PER_COL).round().max(0.0) as i32;
+        if col > LANE_WIDTH {
+            break;
+        }
+        frame.move_rc(row, col);
+        frame.print_char('o');
+        beat += 1;
+    }
+}
+
+/// Blinks on every beat at the given position in beats, with a bigger flash on the downbeats.
+fn draw_beat_flash(frame: &mut Frame, position: f64, divisor: Divisor) {
+    if position.fract() < 0.5 {
+        frame.move_rc(17, 60);
+        if position.floor() as u64 % divisor.taps_per_beat() as u64 == 0 {
+            frame.print("[####]");
+        } else {
+            frame.print("[ ## ]");
+        }
+    }
+}
+
+/// Draws the hit error meter over three rows starting at `row`: ticks, zones, then the mean marker.
+fn draw_hit_errors(frame: &mut Frame, errors: &HitErrors, now: Instant, row: i32) {
+    let windows = &errors.windows;
+
+    frame.move_rc(row + 1, 0);
+    frame.print("early");
+    frame.move_rc(row + 1, HIT_ERROR_LEFT);
+    for col in 0..=HIT_ERROR_HALF_WIDTH * 2 {
+        let offset = (col - HIT_ERROR_HALF_WIDTH) as f64 / HIT_ERROR_HALF_WIDTH as f64 * windows.meh;
+        let color = match windows.judge(offset) {
+            Judgement::Great => *COLOR_GREAT,
+            Judgement::Good => *COLOR_GOOD,
+            _ => *COLOR_MEH,
+        };
+        frame.set_color_pair(color);
+        frame.print_char(if col == HIT_ERROR_HALF_WIDTH { '+' } else { '-' });
+    }
+    frame.set_color_pair(*COLOR_NORMAL);
+    frame.move_rc(row + 1, HIT_ERROR_LEFT + HIT_ERROR_HALF_WIDTH * 2 + 2);
+    frame.print("late");
+
+    // Oldest first so that fresh ticks are drawn on top.
+    for (age, offset) in errors.recent(now) {
+        frame.move_rc(row, hit_error_column(offset, windows));
+        let fade = age / HitErrors::LIFETIME;
+        frame.set_bold(fade < 0.25);
+        frame.print_char(if fade < 0.66 { '|' } else { '.' });
+    }
+    frame.set_bold(false);
+
+    if let Some(mean) = errors.mean(now) {
+        frame.move_rc(row + 2, hit_error_column(mean, windows));
+        frame.print_char('^');
+        frame.move_rc(row + 2, HIT_ERROR_LEFT + HIT_ERROR_HALF_WIDTH * 2 + 2);
+        frame.print(format!("{:+.1}ms", mean));
+    }
+}
+
+// Rows of bars in the result charts, and columns taken by their axis labels.
+const GRAPH_HEIGHT: i32 = 8;
+const GRAPH_AXIS: i32 = 6;
+pub const BPM_GRAPH_COLUMNS: usize = 48;
+pub const HISTOGRAM_BINS: usize = 16;
+const HISTOGRAM_BAR_WIDTH: i32 = 2;
+
+/// Draws the BPM of every slice of the session as bars, with the title on `row` and the time axis below them.
+fn draw_bpm_graph(curses: &mut EasyCurses, timeline: &[Option<f64>], duration: f64, row: i32, col: i32) {
+    curses.move_rc(row, col);
+    curses.print("BPM over time");
+    let values = timeline.iter().flatten().cloned().collect::<Vec<_>>();
+    if values.is_empty() {
+        curses.move_rc(row + 1, col);
+        curses.print("Not enough taps.");
+        return;
+    }
+    let mut low = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let mut high = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    // Keeps a steady session from looking like it was all over the place.
+    if high - low < 10.0 {
+        let mid = (high + low) / 2.0;
+        low = mid - 5.0;
+        high = mid + 5.0;
+    }
+    curses.move_rc(row + 1, col);
+    curses.print(format!("{:>5.0}", high));
+    curses.move_rc(row + GRAPH_HEIGHT, col);
+    curses.print(format!("{:>5.0}", low));
+    curses.set_color_pair(*COLOR_GREAT);
+    for (i, bpm) in timeline.iter().enumerate() {
+        if let Some(bpm) = bpm {
+            let height = 1 + ((bpm - low) / (high - low) * (GRAPH_HEIGHT - 1) as f64).round() as i32;
+            for h in 0..height {
+                curses.move_rc(row + GRAPH_HEIGHT - h, col + GRAPH_AXIS + i as i32);
+                curses.print_char('#');
+            }
+        }
+    }
+    curses.set_color_pair(*COLOR_NORMAL);
+    let end = format!("{:.0}s", duration);
+    curses.move_rc(row + GRAPH_HEIGHT + 1, col + GRAPH_AXIS);
+    curses.print("0s");
+    curses.move_rc(row + GRAPH_HEIGHT + 1, col + GRAPH_AXIS + timeline.len() as i32 - end.len() as i32);
+    curses.print(end);
+}
+
+// Rows on each side of the zero line of the drift graph.
+pub const DRIFT_GRAPH_HALF: i32 = 4;
+
+/// Draws the mean hit offset of every slice of the session as bars going up when late and down when early,
+/// with the title on `row` and the time axis below them.
+fn draw_drift_graph(curses: &mut EasyCurses, timeline: &[Option<f64>], duration: f64, row: i32, col: i32) {
+    curses.move_rc(row, col);
+    curses.print("Hit offset over time (late up, early down)");
+    let zero = row + 1 + DRIFT_GRAPH_HALF;
+    // Small drifts still get a scale that doesn't blow them out of proportion.
+    let scale = timeline.iter().flatten().fold(10.0, |scale: f64, offset| scale.max(offset.abs()));
+    curses.move_rc(row + 1, col);
+    curses.print(format!("{:>+5.0}", scale));
+    curses.move_rc(zero, col);
+    curses.print(format!("{:>5}", "0ms"));
+    curses.move_rc(zero + DRIFT_GRAPH_HALF, col);
+    curses.print(format!("{:>+5.0}", -scale));
+    for (i, offset) in timeline.iter().enumerate() {
+        let x = col + GRAPH_AXIS + i as i32;
+        curses.move_rc(zero, x);
+        curses.print_char('-');
+        if let Some(offset) = offset {
+            let height = (offset.abs() / scale * DRIFT_GRAPH_HALF as f64).round() as i32;
+            curses.set_color_pair(if *offset < 0.0 { *COLOR_GOOD } else { *COLOR_MEH });
+            for h in 1..=height.min(DRIFT_GRAPH_HALF) {
+                curses.move_rc(if *offset < 0.0 { zero + h } else { zero - h }, x);
+                curses.print_char('#');
+            }
+            curses.set_color_pair(*COLOR_NORMAL);
+        }
+    }
+    let end = format!("{:.0}s", duration);
+    curses.move_rc(zero + DRIFT_GRAPH_HALF + 1, col + GRAPH_AXIS);
+    curses.print("0s");
+    curses.move_rc(zero + DRIFT_GRAPH_HALF + 1, col + GRAPH_AXIS + timeline.len() as i32 - end.len() as i32);
+    curses.print(end);
+}
+
+/// Draws how the intervals between taps were spread, like `draw_bpm_graph`.
+fn draw_interval_histogram(curses: &mut EasyCurses, histogram: &Histogram, row: i32, col: i32) {
+    curses.move_rc(row, col);
+    curses.print("Intervals");
+    let highest = histogram.counts.iter().cloned().max().unwrap_or(0).max(1);
+    curses.move_rc(row + 1, col);
+    curses.print(format!("{:>5}", highest));
+    curses.set_color_pair(*COLOR_GOOD);
+    for (i, count) in histogram.counts.iter().enumerate() {
+        let height = (*count as f64 / highest as f64 * GRAPH_HEIGHT as f64).ceil() as i32;
+        for h in 0..height {
+            curses.move_rc(row + GRAPH_HEIGHT - h, col + GRAPH_AXIS + i as i32 * HISTOGRAM_BAR_WIDTH);
+            curses.print("#".repeat(HISTOGRAM_BAR_WIDTH as usize - 1));
+        }
+    }
+    curses.set_color_pair(*COLOR_NORMAL);
+    let end = format!("{:.0}ms", histogram.max());
+    let width = histogram.counts.len() as i32 * HISTOGRAM_BAR_WIDTH;
+    curses.move_rc(row + GRAPH_HEIGHT + 1, col + GRAPH_AXIS);
+    curses.print(format!("{:.0}ms", histogram.min));
+    curses.move_rc(row + GRAPH_HEIGHT + 1, col + GRAPH_AXIS + width - end.len() as i32);
+    curses.print(end);
+}
+
+const PAUSE_ROW: i32 = 10;
+const PAUSE_WIDTH: usize = 44;
+
+/// Draws a box over the middle of the practice screen with the keys to get out of the pause.
+fn draw_pause_overlay(frame: &mut Frame, keymap: &Keymap) {
+    let lines = [
+        String::new(),
+        "Paused".to_string(),
+        String::new(),
+        format!("{}: resume", keymap.names(Slot::Pause).join("/")),
+        format!("{}: restart", keymap.names(Slot::Reset).join("/")),
+        "escape: end the session".to_string(),
+        String::new(),
+    ];
+    frame.set_color_pair(*COLOR_TITLE);
+    for (i, line) in lines.iter().enumerate() {
+        frame.move_rc(PAUSE_ROW + i as i32, 4);
+        frame.print(format!(" {:<width$}", line, width = PAUSE_WIDTH - 1));
+    }
+    frame.set_color_pair(*COLOR_NORMAL);
 }
 
 pub struct CursesRenderSystem;
 
 impl<'a> System<'a> for CursesRenderSystem {
     type SystemData = (
-        WriteExpect<'a, Curses>,
-        ReadExpect<'a, CircularBuffer<Instant>>,
+        ReadExpect<'a, Curses>,
+        ReadExpect<'a, Tempo>,
         Read<'a, Stats>,
+        Option<Read<'a, Metronome>>,
+        Option<Read<'a, StepTest>>,
+        ReadExpect<'a, HitErrors>,
+        Option<Read<'a, Recorder>>,
+        Option<Read<'a, Player>>,
+        Read<'a, Keymap>,
+        Read<'a, Warnings>,
+        Read<'a, Paused>,
+        Read<'a, SessionLimit>,
+        Read<'a, Screen>,
+        Read<'a, Technique>,
+        Read<'a, Clock>,
+        ReadExpect<'a, InputPoller>,
+        Option<Read<'a, Evdev>>,
     );
-    fn run(&mut self, (mut curses, buf, stats): Self::SystemData) {
-        let curses = &mut curses.0;
+    fn run(
+        &mut self,
+        (
+            curses,
+            tempo,
+            stats,
+            metronome,
+            step_test,
+            hit_errors,
+            recorder,
+            player,
+            keymap,
+            warnings,
+            paused,
+            limit,
+            screen,
+            technique,
+            clock,
+            poller,
+            evdev,
+        ): Self::SystemData,
+    ) {
+        // The other screens are drawn by their states.
+        if *screen != Screen::Playing && *screen != Screen::Paused {
+            return;
+        }
+        let now = clock.now();
+        // Drawn before taking the terminal, which the input thread is waiting on.
+        let mut frame = Frame::new(100, 100, *COLOR_NORMAL);
+
+        if let Some(avg) = tempo.interval() {
+            frame.move_rc(0, 0);
+            frame.print(format!("Average delay between presses: {:.1}ms ({})", avg * 1000.0, tempo.window()));
+            frame.move_rc(1, 0);
+            frame.print(format!("KPS: {:.2}", 1.0 / avg));
+            if let Some(bpm) = tempo.bpm() {
+                frame.move_rc(2, 0);
+                frame.print(format!("BPM: {:.1} ({} snap)", bpm, tempo.divisor()));
+            }
+        }
+
+        if stats.total > 0 {
+            frame.move_rc(4, 0);
+            frame.print(format!("Total Presses: {}", stats.total));
+            frame.move_rc(5, 0);
+            frame.print(format!("Combo: {}", stats.combo));
+            frame.move_rc(6, 0);
+            frame.print(format!("Score: {}", stats.score));
+
+            for (i, key) in [Key::Left, Key::Right, Key::Extra].iter().enumerate() {
+                if let Some(k) = stats.keys.get(key) {
+                    frame.move_rc(8 + i as i32, 0);
+                    match k.avg_interval() {
+                        Some(avg) => frame.print(format!("{:?}: {} presses, {:.1}ms between presses", key, k.presses, avg * 1000.0)),
+                        None => frame.print(format!("{:?}: {} presses", key, k.presses)),
+                    };
+                    if let Some(hold) = k.avg_hold() {
+                        frame.print(format!(", held for {:.1}ms", hold * 1000.0));
+                    }
+                }
+            }
+            if let Some(balance) = stats.balance() {
+                frame.move_rc(11, 0);
+                frame.print(format!("Balance: {:.1}% L / {:.1}% R", balance * 100.0, (1.0 - balance) * 100.0));
+            }
+            if let Some(gallop) = stats.gallop.describe() {
+                frame.move_rc(7, 0);
+                frame.print(gallop);
+            }
+            if *technique != Technique::Any {
+                // Lights up for a moment after every wrong key.
+                let fresh = stats
+                    .last_technique_error
+                    .map(|time| now.duration_since(time).as_secs_f64() < TECHNIQUE_ERROR_FLASH)
+                    .unwrap_or(false);
+                frame.move_rc(12, 0);
+                if fresh {
+                    frame.set_color_pair(*COLOR_ERROR);
+                    frame.set_bold(true);
+                }
+                frame.print(format!("Technique: {}, {} wrong keys", *technique, stats.technique_errors));
+                frame.set_color_pair(*COLOR_NORMAL);
+                frame.set_bold(false);
+            }
 
-        // Clear the screen
-        curses.set_color_pair(*COLOR_NORMAL);
-        for y in 0..100 {
-            for x in 0..100 {
-                curses.move_rc(y as i32, x as i32);
-                curses.print_char(' ');
+            if let Some(intervals) = &stats.intervals {
+                frame.move_rc(13, 0);
+                frame.print(format!("UR: {:.2}", intervals.unstable_rate));
+                frame.move_rc(14, 0);
+                frame.print(format!("Interval std dev: {:.2}ms", intervals.std_dev));
+                frame.move_rc(15, 0);
+                frame.print(format!(
+                    "Interval min/median/max: {:.1}ms / {:.1}ms / {:.1}ms",
+                    intervals.min, intervals.median, intervals.max
+                ));
             }
         }
 
-        if let Some(start) = buf.queue().front() {
-            let mut avg: f64 = buf.queue().iter().skip(1).map(|e| e.duration_since(*start).as_secs_f64()).sum();
-            if avg > 0.01 {
-                avg = avg / (buf.queue().len() - 1) as f64;
+        if let Some(metronome) = metronome {
+            frame.move_rc(17, 0);
+            match &metronome.pattern {
+                Pattern::Fixed { bpm, divisor } => {
+                    frame.print(format!("Metronome: {} BPM at {} snap, OD {}", bpm, divisor, metronome.od));
+                    if let Some(elapsed) = metronome.elapsed(now) {
+                        draw_beat_flash(&mut frame, elapsed / 1000.0 / divisor.interval(*bpm), *divisor);
+                    }
+                }
+                Pattern::Ramp(ramp) => {
+                    let step = ramp.step_of(metronome.next_beat());
+                    frame.print(format!(
+                        "Step test: {} BPM at {} snap, step {} ({}/{} notes), OD {}",
+                        ramp.bpm(step),
+                        ramp.divisor,
+                        step + 1,
+                        metronome.next_beat() - step * ramp.notes,
+                        ramp.notes,
+                        metronome.od
+                    ));
+                    if let Some(elapsed) = metronome.elapsed(now) {
+                        draw_beat_flash(&mut frame, ramp.position(elapsed), ramp.divisor);
+                    }
+                    let completed = step_test.as_ref().map(|test| test.completed()).unwrap_or_default();
+                    if let Some(last) = completed.last() {
+                        frame.move_rc(16, 0);
+                        frame.print(format!(
+                            "Last step: {} BPM, UR {}, {} accuracy, {}",
+                            ramp.bpm(completed.len() - 1),
+                            last.unstable_rate().map(|ur| format!("{:.1}", ur)).unwrap_or_else(|| "-".to_string()),
+                            last.judgements.accuracy().map(|acc| format!("{:.2}%", acc * 100.0)).unwrap_or_else(|| "-".to_string()),
+                            if last.passed() { "passed" } else { "failed" }
+                        ));
+                    }
+                }
+                Pattern::Rounds(rounds) => {
+                    frame.print(format!(
+                        "Interval training: {} BPM at {} snap, {} rounds of {} notes with {}s rests, OD {}",
+                        rounds.bpm, rounds.divisor, rounds.rounds, rounds.notes, rounds.rest, metronome.od
+                    ));
+                    frame.move_rc(16, 0);
+                    match metronome.elapsed(now).map(|elapsed| rounds.phase(elapsed)) {
+                        None => frame.print(format!("Start the first of {} rounds whenever you're ready.", rounds.rounds)),
+                        Some(Phase::Stream { round, position }) => {
+                            let left = ((round + 1) * rounds.notes).saturating_sub(metronome.next_beat().max(round * rounds.notes));
+                            draw_beat_flash(&mut frame, position, rounds.divisor);
+                            frame.move_rc(16, 0);
+                            frame.print(format!("Round {}/{}: STREAM, {} notes left", round + 1, rounds.rounds, left))
+                        }
+                        Some(Phase::Rest { round, left }) => frame.print(format!(
+                            "Round {}/{}: REST, next stream in {:.1}s",
+                            round + 1,
+                            rounds.rounds,
+                            left
+                        )),
+                        Some(Phase::Done) => frame.print("All rounds done!"),
+                    };
+                }
+                Pattern::Chart(chart) => {
+                    let elapsed = metronome.elapsed(now).unwrap_or(0.0);
+                    frame.print(format!(
+                        "Chart: {} ({}/{} notes), OD {}",
+                        chart.name,
+                        metronome.next_beat(),
+                        chart.notes.len(),
+                        metronome.od
+                    ));
+                    if let Some(bpm) = chart.bpm_at(elapsed) {
+                        frame.move_rc(17, 80);
+                        frame.print(format!("{:.0} BPM", bpm));
+                    }
+                    if metronome.is_finished() {
+                        frame.move_rc(16, 0);
+                        if metronome.looping {
+                            frame.print("Loop done! Keep tapping to go again.");
+                        } else {
+                            frame.print("Chart finished! Take a break to play it again.");
+                        }
+                    } else {
+                        draw_chart_lane(&mut frame, &metronome, elapsed, 16);
+                    }
+                }
+            }
+            let judgements = &stats.judgements;
+            frame.move_rc(18, 0);
+            frame.print(format!(
+                "300: {}  100: {}  50: {}  Miss: {}",
+                judgements.great, judgements.good, judgements.meh, judgements.miss
+            ));
+            if let Some(accuracy) = judgements.accuracy() {
+                frame.move_rc(19, 0);
+                frame.print(format!("Accuracy: {:.2}%", accuracy * 100.0));
+            }
+            if let Some(hit) = &stats.last_hit {
+                frame.move_rc(20, 0);
+                frame.print(format!("Last hit: {} ({:+.1}ms)", hit.judgement, hit.offset));
+            }
+            if let Some(drift) = recent_drift(&stats) {
+                let arrow = match Tendency::of(drift) {
+                    Tendency::Rushing => "<<",
+                    Tendency::OnTime => "==",
+                    Tendency::Dragging => ">>",
+                };
+                frame.move_rc(21, 0);
+                frame.print(format!(
+                    "Drift: {} {:+.1}ms over the last {} hits, {}",
+                    arrow,
+                    drift,
+                    DRIFT_WINDOW,
+                    Tendency::of(drift)
+                ));
+            }
+        }
+
+        draw_hit_errors(&mut frame, &hit_errors, now, 22);
+
+        // Taps are timestamped when read, so they can be this late on top of the terminal's own delay.
+        let timestamps = match (&player, &evdev) {
+            (Some(_), _) => None,
+            (None, Some(evdev)) => Some(match evdev.error() {
+                Some(e) => format!("Reading {} failed: {}", evdev.path, e),
+                None if evdev.is_recording() => format!("Input timestamps: from the recording {}", evdev.path),
+                None => format!("Input timestamps: from the kernel, reading {}", evdev.path),
+            }),
+            (None, None) => Some(format!("Input timestamps: {}", poller.jitter())),
+        };
+        if let Some(timestamps) = timestamps {
+            frame.move_rc(25, 0);
+            frame.print(timestamps);
+        }
+
+        frame.move_rc(26, 0);
+        if let Some(recorder) = recorder {
+            match &recorder.error {
+                Some(e) => frame.print(format!("Recording to {} failed: {}", recorder.path, e)),
+                None => frame.print(format!("Recording to {}", recorder.path)),
+            };
+        }
+        if let Some(player) = player {
+            if player.is_finished() {
+                frame.print(format!("Finished playing {} back", player.path));
+            } else {
+                frame.print(format!(
+                    "Playing {} back: {:.0}% of {:.1}s",
+                    player.path,
+                    player.progress() * 100.0,
+                    player.session().duration().as_secs_f64()
+                ));
             }
-            curses.move_rc(0, 0);
-            curses.print(format!("Average delay between presses: {}", avg));
-            curses.move_rc(1, 0);
-            curses.print(format!("KPS: {}", 1.0/avg));
-            curses.move_rc(2, 0);
-            curses.print(format!("BPM: {}", (1.0/avg) * 60.0));
+        }
+
+        frame.move_rc(27, 0);
+        if let (Some(duration), Some(first)) = (limit.duration, stats.first_tap) {
+            let played = paused.0.unwrap_or(now).duration_since(first);
+            frame.print(format!("Time left: {:.0}s", duration.checked_sub(played).unwrap_or_default().as_secs_f64()));
+        }
+        if let Some(taps) = limit.taps {
+            frame.print(format!("Taps: {}/{}", stats.total, taps));
+        }
+
+        frame.move_rc(28, 0);
+        let bindings = Slot::ALL
+            .iter()
+            .map(|slot| (slot, keymap.names(*slot)))
+            .filter(|(_, names)| !names.is_empty())
+            .map(|(slot, names)| format!("{}: {}", slot, names.join(" ")))
+            .collect::<Vec<_>>();
+        frame.print(format!("Keys: {}  quit: escape", bindings.join("  ")));
+        for (i, warning) in warnings.0.iter().enumerate() {
+            frame.move_rc(29 + i as i32, 0);
+            frame.print(warning);
+        }
 
-            curses.move_rc(4, 0);
-            curses.print(format!("Total Presses: {}", stats.total));
-            curses.move_rc(5, 0);
-            curses.print(format!("Combo: {}", stats.combo));
-            curses.move_rc(6, 0);
-            curses.print(format!("Score: {}", stats.score));
+        if *screen == Screen::Paused {
+            draw_pause_overlay(&mut frame, &keymap);
         }
 
         // Render
-        curses.refresh();
+        frame.render(&mut curses.lock());
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub enum InputEvent {
-    Input,
-}
+pub struct CursesInputSystem;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Keymap {
-    pub map: HashMap<Input, InputEvent>,
+impl<'a> System<'a> for CursesInputSystem {
+    type SystemData = (
+        Write<'a, EventChannel<InputEvent>>,
+        ReadExpect<'a, InputPoller>,
+        Option<Read<'a, Evdev>>,
+        Read<'a, Keymap>,
+        Option<Read<'a, Player>>,
+        Read<'a, Screen>,
+        Read<'a, Clock>,
+        Read<'a, Paused>,
+    );
+    fn run(&mut self, (mut input_ev, poller, evdev, keymap, player, screen, clock, paused): Self::SystemData) {
+        // Taps come from the recording during a playback, and from the input device when there is one.
+        let tapping = *screen == Screen::Playing && player.is_none();
+        for (bindings, time) in poller.drain() {
+            if bindings == [Binding::Input(Input::Character('\u{1b}'))] {
+                input_ev.single_write(InputEvent::Quit);
+                continue;
+            }
+            for binding in bindings {
+                input_ev.single_write(InputEvent::Press(binding));
+                match keymap.map.get(&binding) {
+                    Some(Slot::Tap(key)) if tapping && evdev.is_none() => {
+                        input_ev.single_write(InputEvent::Input(*key, time));
+                    }
+                    Some(Slot::Pause) => input_ev.single_write(InputEvent::Pause),
+                    Some(Slot::Reset) => input_ev.single_write(InputEvent::Reset),
+                    _ => {}
+                }
+            }
+        }
+        // The menus still go through the terminal, which sees the same keys. Recordings hold still during pauses.
+        let until = paused.0.unwrap_or_else(|| clock.now());
+        for event in evdev.iter().flat_map(|evdev| evdev.drain(until)) {
+            match keymap.map.get(&event.binding) {
+                Some(Slot::Tap(key)) if tapping && event.pressed => {
+                    input_ev.single_write(InputEvent::Input(*key, event.time))
+                }
+                Some(Slot::Tap(key)) if tapping => input_ev.single_write(InputEvent::Release(*key, event.time)),
+                _ => {}
+            }
+        }
+    }
 }
 
-impl Default for Keymap {
-    fn default() -> Self {
-        Keymap {
-            map: [
-                (Input::Character('x'), InputEvent::Input),
-                (Input::Character('b'), InputEvent::Input),
-            ].iter().cloned().collect(),
+/// Feeds the taps and releases of a recorded session in place of the keyboard.
+pub struct PlaybackSystem;
+
+impl<'a> System<'a> for PlaybackSystem {
+    type SystemData = (
+        Write<'a, EventChannel<InputEvent>>,
+        Option<Write<'a, Player>>,
+        Read<'a, Screen>,
+        Read<'a, Clock>,
+    );
+    fn run(&mut self, (mut input_ev, player, screen, clock): Self::SystemData) {
+        let mut player = match player {
+            Some(player) if *screen == Screen::Playing => player,
+            _ => return,
+        };
+        for event in player.poll(clock.now()) {
+            input_ev.single_write(event);
         }
     }
 }
 
-pub struct CursesInputSystem;
+#[derive(Default)]
+pub struct SessionRecordSystem {
+    reader: Option<ReaderId<InputEvent>>,
+}
 
-impl<'a> System<'a> for CursesInputSystem {
+impl<'a> System<'a> for SessionRecordSystem {
     type SystemData = (
         Write<'a, EventChannel<InputEvent>>,
-        WriteExpect<'a, Curses>,
-        Read<'a, Keymap>,
+        Option<Write<'a, Recorder>>,
     );
-    fn run(&mut self, (mut input_ev, mut curses, keymap): Self::SystemData) {
-        let curses = &mut curses.0;
-        while let Some(input) = curses.get_input() {
-            if let Some(ev) = keymap.map.get(&input) {
-                input_ev.single_write(*ev);
+    fn run(&mut self, (mut input_ev, recorder): Self::SystemData) {
+        if self.reader.is_none() {
+            self.reader = Some(input_ev.register_reader());
+        }
+        let events = input_ev.read(&mut self.reader.as_mut().unwrap());
+        if let Some(mut recorder) = recorder {
+            for ev in events {
+                match ev {
+                    InputEvent::Input(key, time) => recorder.record(*key, *time),
+                    InputEvent::Release(key, time) => recorder.release(*key, *time),
+                    _ => {}
+                }
             }
+            recorder.flush();
         }
     }
 }
 
+/// Judges taps at the time they were read, which every `InputEvent::Input` carries, and never at the time they are
+/// handled. That's why it doesn't need the `Clock`: the same taps score the same whenever the frame gets to them.
 #[derive(Default)]
 pub struct OsuInputSystem {
     reader: Option<ReaderId<InputEvent>>,
@@ -123,67 +710,255 @@ impl<'a> System<'a> for OsuInputSystem {
     type SystemData = (
         Write<'a, EventChannel<InputEvent>>,
         Write<'a, Stats>,
-        WriteExpect<'a, CircularBuffer<Instant>>,
+        WriteExpect<'a, Tempo>,
+        Option<Write<'a, Metronome>>,
+        Option<Write<'a, StepTest>>,
+        WriteExpect<'a, HitErrors>,
+        Read<'a, Technique>,
     );
-    fn run(&mut self, (mut input_ev, mut stats, mut buf): Self::SystemData) {
+    fn run(
+        &mut self,
+        (mut input_ev, mut stats, mut tempo, mut metronome, mut step_test, mut hit_errors, technique): Self::SystemData,
+    ) {
         if self.reader.is_none() {
             self.reader = Some(input_ev.register_reader());
         }
         for ev in input_ev.read(&mut self.reader.as_mut().unwrap()) {
             match ev {
-                InputEvent::Input => {
+                InputEvent::Input(key, time) => {
+                    let now = *time;
+                    let is_break = tempo
+                        .last()
+                        .map(|last| now.duration_since(last).as_secs_f64() > BREAK_THRESHOLD)
+                        .unwrap_or(false);
+                    if let (Some(last), Some(first), false) = (tempo.last(), stats.first_tap, is_break) {
+                        stats.session_intervals.push(now.duration_since(last).as_secs_f64() * 1000.0);
+                        stats.interval_times.push(now.duration_since(first).as_secs_f64());
+                    }
+                    stats.first_tap.get_or_insert(now);
+                    stats.last_tap = Some(now);
                     stats.total += 1;
-                    if let Some(delay) = buf.queue().back() {
-                        if Instant::now().duration_since(*delay).as_secs_f32() > 1.0 {
+                    stats.keys.entry(*key).or_insert_with(KeyStats::default).press(now);
+                    // Alternation starts over after a break.
+                    let previous = if is_break { None } else { stats.last_key };
+                    let wrong_key = !technique.allows(*key, stats.first_key, previous);
+                    if wrong_key {
+                        stats.technique_errors += 1;
+                        stats.last_technique_error = Some(now);
+                    }
+                    match (previous, tempo.last()) {
+                        (Some(previous), Some(last)) => {
+                            let interval = now.duration_since(last).as_secs_f64() * 1000.0;
+                            stats.gallop.push(previous, *key, interval);
+                        }
+                        _ => stats.gallop.interrupt(),
+                    }
+                    stats.first_key.get_or_insert(*key);
+                    stats.last_key = Some(*key);
+                    // Without a metronome, taps are timed against the user's own mean tempo.
+                    let expected = match (tempo.last(), tempo.interval()) {
+                        (Some(last), Some(interval)) if !is_break && metronome.is_none() => {
+                            Some(last + Duration::from_secs_f64(interval))
+                        }
+                        _ => None,
+                    };
+                    if let Some(expected) = expected {
+                        let offset = if now >= expected {
+                            now.duration_since(expected).as_secs_f64()
+                        } else {
+                            -expected.duration_since(now).as_secs_f64()
+                        };
+                        hit_errors.push(now, offset * 1000.0);
+                    }
+                    tempo.push(now);
+                    let intervals = tempo.intervals().iter().map(|i| i * 1000.0).collect::<Vec<_>>();
+                    stats.intervals = IntervalStats::from_intervals(&intervals);
+                    if intervals.len() + 1 >= PEAK_MIN_TAPS {
+                        if let Some(bpm) = tempo.bpm() {
+                            stats.peak_bpm = Some(stats.peak_bpm.map(|peak| peak.max(bpm)).unwrap_or(bpm));
+                        }
+                    }
+
+                    let hit = metronome.as_mut().and_then(|metronome| {
+                        // Steady streams start over after a break, charts and interval training only once they
+                        // are finished. Until then a break only costs combo through the beats it skipped, so
+                        // the rests between rounds are free while stopping in a stream isn't.
+                        let fixed = metronome.pattern.len().is_none();
+                        let finished = metronome.is_finished();
+                        if (is_break && (fixed || finished)) || (metronome.looping && finished) {
+                            metronome.restart();
+                        }
+                        metronome.judge(now)
+                    });
+                    if let Some(hit) = hit {
+                        for _ in 0..hit.skipped {
+                            stats.judgements.add(Judgement::Miss);
+                        }
+                        stats.judgements.add(hit.judgement);
+                        stats.last_hit = Some(hit);
+                        if hit.judgement != Judgement::Miss {
+                            let since_first = stats.first_tap.map(|first| now.duration_since(first).as_secs_f64());
+                            stats.hit_offsets.push(hit.offset);
+                            stats.hit_times.push(since_first.unwrap_or(0.0));
+                        }
+                        if let (Some(test), Some(metronome)) = (step_test.as_mut(), metronome.as_ref()) {
+                            test.push(&hit, metronome.next_beat());
+                        }
+                        hit_errors.push(now, hit.offset);
+                        if hit.skipped > 0 || hit.judgement == Judgement::Miss || wrong_key {
                             stats.combo = 0;
                         }
+                        if hit.judgement != Judgement::Miss {
+                            stats.combo += 1;
+                            stats.score += hit.judgement.score() as u64 * stats.combo as u64;
+                        }
+                    } else {
+                        if is_break || wrong_key {
+                            stats.combo = 0;
+                        }
+                        stats.combo += 1;
+                        stats.score += stats.combo as u64;
                     }
-                    buf.push(Instant::now());
-                    stats.combo += 1;
-                    stats.score += stats.combo as u64;
+                    stats.max_combo = stats.max_combo.max(stats.combo);
+                },
+                InputEvent::Release(key, time) => {
+                    stats.keys.entry(*key).or_insert_with(KeyStats::default).release(*time);
                 },
+                _ => {},
             }
         }
     }
 }
 
-pub struct InitState;
-
-impl SimpleState for InitState {
-    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
-        println!("Game started!");
-
-        let mut curses = EasyCurses::initialize_system().expect("Failed to start ncurses.");
-        curses.set_input_mode(InputMode::Character);
-        curses.set_keypad_enabled(true);
-        curses.set_echo(false);
-        curses.set_cursor_visibility(CursorVisibility::Invisible);
-        curses.set_input_timeout(TimeoutMode::Immediate);
-        #[cfg(unix)]
-        unsafe{ ncurses::ll::set_escdelay(0) };
-
-        curses.refresh();
-
-        data.world.insert(Curses(curses));
-        data.world.insert(CircularBuffer::<Instant>::new(8));
+fn print_streams(beatmap: &Beatmap, streams: &[Stream], divisor: Divisor) {
+    println!("{}", beatmap.name());
+    println!("   #      Start  Notes     BPM  Kind");
+    for (i, stream) in streams.iter().enumerate() {
+        println!(
+            "{:>4}  {:>9}  {:>5}  {:>6.1}  {}",
+            i + 1,
+            format_time(stream.start),
+            stream.notes,
+            stream.bpm(divisor),
+            stream.kind()
+        );
     }
+}
 
-    fn update(&mut self, _data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
-        Trans::None
+/// Asks which of the listed streams to practice. `None` if the user just pressed enter.
+fn pick_stream(count: usize) -> amethyst::Result<Option<usize>> {
+    loop {
+        print!("Stream to practice (1-{}, enter to quit): ", count);
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        match line.parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => return Ok(Some(n)),
+            _ => println!("\"{}\" is not in the list.", line),
+        }
     }
 }
 
 fn main() -> amethyst::Result<()> {
     amethyst::start_logger(Default::default());
 
+    let cli = std::env::args().skip(1).collect::<Vec<_>>();
+    let mut options = Options::parse(cli.clone()).map_err(amethyst::Error::from_string)?;
+    let mut replay = None;
+    if let Some(path) = options.play.clone() {
+        let session = Session::load(Path::new(&path)).map_err(|e| amethyst::Error::from_string(e.to_string()))?;
+        // Play the session with the options it was recorded with, unless overridden.
+        options = Options::recorded(session.args.clone()).map_err(amethyst::Error::from_string)?;
+        options.apply(cli).map_err(amethyst::Error::from_string)?;
+        replay = Some((path, session));
+    }
+
+    let metronome = match options.chart.clone() {
+        Some(path) => {
+            let beatmap = Beatmap::load(Path::new(&path)).map_err(|e| amethyst::Error::from_string(e.to_string()))?;
+            let min_bpm = options.min_bpm;
+            let streams = beatmap
+                .streams()
+                .into_iter()
+                .filter(|s| s.bpm(options.divisor) >= min_bpm)
+                .collect::<Vec<_>>();
+            if options.list_streams {
+                if streams.is_empty() {
+                    println!("No streams found in \"{}\".", path);
+                    return Ok(());
+                }
+                print_streams(&beatmap, &streams, options.divisor);
+                options.stream = pick_stream(streams.len())?;
+                options.list_streams = false;
+                if options.stream.is_none() {
+                    return Ok(());
+                }
+            }
+            let stream = match options.stream {
+                Some(n) => Some(streams.get(n - 1).ok_or_else(|| {
+                    amethyst::Error::from_string(format!("There are only {} streams in \"{}\"", streams.len(), path))
+                })?),
+                None => None,
+            };
+            let chart = match stream {
+                Some(stream) => beatmap.chart(Some(stream.start), Some(stream.end)),
+                None => beatmap.chart(options.from, options.to),
+            };
+            if chart.notes.is_empty() {
+                return Err(amethyst::Error::from_string(format!("No notes to play in \"{}\"", path)));
+            }
+            let od = options.od.unwrap_or(beatmap.od);
+            let mut metronome = Metronome::new(Pattern::Chart(chart), od);
+            metronome.looping = stream.is_some();
+            Some(metronome)
+        }
+        None => match (options.step, options.intervals) {
+            (Some(bpm), _) => {
+                let notes = options.step_notes.unwrap_or(DEFAULT_STEP_NOTES);
+                let ramp = Ramp::new(bpm, notes, options.divisor);
+                Some(Metronome::new(Pattern::Ramp(ramp), options.od.unwrap_or(DEFAULT_OD)))
+            }
+            (None, Some(bpm)) => {
+                let rounds = Rounds::new(bpm, &options);
+                Some(Metronome::new(Pattern::Rounds(rounds), options.od.unwrap_or(DEFAULT_OD)))
+            }
+            (None, None) => options
+                .metronome
+                .map(|bpm| Metronome::fixed(bpm, options.divisor, options.od.unwrap_or(DEFAULT_OD))),
+        },
+    };
+
+    // Sessions set up on the command line start right away.
+    let launch = if options.rebind {
+        Launch::Keys
+    } else if metronome.is_some() || replay.is_some() || options.stamina || options.burst.is_some() || options.training {
+        Launch::Practice
+    } else {
+        Launch::Menu
+    };
+    let practice = Practice {
+        options,
+        metronome,
+        replay,
+        training: None,
+    };
+
     let app_root = application_root_dir()?;
     let assets_dir = app_root.join("assets/");
 
+    let input_systems = ["curses_input", "playback"];
     let game_data = GameDataBuilder::default()
         .with(CursesInputSystem, "curses_input", &[])
-        .with(OsuInputSystem::default(), "osu_input", &["curses_input"])
+        .with(PlaybackSystem, "playback", &["curses_input"])
+        .with(SessionRecordSystem::default(), "session_record", &input_systems)
+        .with(OsuInputSystem::default(), "osu_input", &input_systems)
         .with(CursesRenderSystem, "curses_render", &["osu_input"]);
-    let mut game = Application::build(assets_dir, InitState)?
+    let state = MenuState::new(practice, launch);
+    let mut game = Application::build(assets_dir, state)?
         .with_frame_limit(
             FrameRateLimitStrategy::SleepAndYield(Duration::from_millis(2)),
             60,
@@ -193,3 +968,99 @@ fn main() -> amethyst::Result<()> {
     Ok(())
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world(metronome: Option<Metronome>) -> (World, Instant) {
+        let start = Instant::now();
+        let divisor = Divisor::new(4).unwrap();
+        let mut world = World::new();
+        world.insert(Clock::Manual(start));
+        world.insert(EventChannel::<InputEvent>::new());
+        world.insert(Stats::default());
+        world.insert(Tempo::new(Window::Taps(8), divisor));
+        world.insert(HitErrors::new(HitWindows::from_od(DEFAULT_OD)));
+        world.insert(Technique::default());
+        if let Some(metronome) = metronome {
+            world.insert(metronome);
+        }
+        (world, start)
+    }
+
+    /// Taps at the given milliseconds after `start`, all handled in a single run.
+    fn tap(world: &World, system: &mut OsuInputSystem, start: Instant, taps: &[(Key, u64)]) {
+        // The first run only starts listening.
+        system.run_now(world);
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        world
+            .write_resource::<EventChannel<InputEvent>>()
+            .iter_write(taps.iter().map(|&(key, ms)| InputEvent::Input(key, at(ms))));
+        system.run_now(world);
+    }
+
+    fn stream(times: &[u64]) -> Vec<(Key, u64)> {
+        let keys = [Key::Left, Key::Right];
+        times.iter().enumerate().map(|(i, &ms)| (keys[i % 2], ms)).collect()
+    }
+
+    #[test]
+    fn free_tapping_combo_starts_over_after_a_break() {
+        let (world, start) = world(None);
+        let mut system = OsuInputSystem::default();
+        tap(&world, &mut system, start, &stream(&[0, 100, 200, 300, 2000, 2100]));
+        let stats = world.read_resource::<Stats>();
+        assert_eq!(stats.total, 6);
+        assert_eq!(stats.combo, 2);
+        assert_eq!(stats.max_combo, 4);
+        assert_eq!(stats.score, 1 + 2 + 3 + 4 + 1 + 2);
+        assert_eq!(stats.judgements.total(), 0);
+        // The break isn't an interval of the stream.
+        assert_eq!(stats.session_intervals.len(), 4);
+        assert_eq!(stats.first_tap, Some(start));
+        assert_eq!(stats.last_tap, Some(start + Duration::from_millis(2100)));
+    }
+
+    #[test]
+    fn metronome_judges_taps_at_their_own_time() {
+        // 100ms between beats.
+        let metronome = Metronome::fixed(150.0, Divisor::new(4).unwrap(), DEFAULT_OD);
+        let (world, start) = world(Some(metronome));
+        let mut system = OsuInputSystem::default();
+        tap(&world, &mut system, start, &stream(&[0, 100, 200, 300]));
+        let stats = world.read_resource::<Stats>();
+        assert_eq!(stats.judgements, Judgements { great: 4, ..Default::default() });
+        assert_eq!(stats.combo, 4);
+        assert_eq!(stats.score, 300 * (1 + 2 + 3 + 4));
+        assert_eq!(stats.hit_offsets.iter().map(|o| o.round()).collect::<Vec<_>>(), vec![0.0; 4]);
+    }
+
+    #[test]
+    fn skipped_beats_break_the_combo() {
+        let metronome = Metronome::fixed(150.0, Divisor::new(4).unwrap(), DEFAULT_OD);
+        let (world, start) = world(Some(metronome));
+        let mut system = OsuInputSystem::default();
+        tap(&world, &mut system, start, &stream(&[0, 100, 400, 500]));
+        let stats = world.read_resource::<Stats>();
+        assert_eq!(stats.judgements, Judgements { great: 4, miss: 2, ..Default::default() });
+        assert_eq!(stats.combo, 2);
+        assert_eq!(stats.max_combo, 2);
+        assert_eq!(stats.score, 300 * (1 + 2 + 1 + 2));
+        assert_eq!(stats.last_hit.map(|hit| hit.skipped), Some(0));
+    }
+
+    #[test]
+    fn taps_handled_over_several_frames_score_the_same() {
+        let taps = stream(&[0, 100, 400, 500]);
+        let metronome = Metronome::fixed(150.0, Divisor::new(4).unwrap(), DEFAULT_OD);
+        let (world, start) = world(Some(metronome));
+        let mut system = OsuInputSystem::default();
+        for tapped in taps.chunks(1) {
+            tap(&world, &mut system, start, tapped);
+        }
+        let stats = world.read_resource::<Stats>();
+        assert_eq!(stats.judgements, Judgements { great: 4, miss: 2, ..Default::default() });
+        assert_eq!((stats.combo, stats.max_combo, stats.score), (2, 2, 300 * (1 + 2 + 1 + 2)));
+    }
+}
@@ -0,0 +1,335 @@
+use crate::input::Technique;
+use crate::stats::{Divisor, Window};
+
+pub const USAGE: &str = "Usage: osu_practice [streams <beatmap.osu> | keys] [options]";
+
+/// Longest timed session in seconds, well within what a `Duration` holds.
+pub const MAX_DURATION: f64 = 24.0 * 60.0 * 60.0;
+
+/// Command line options.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub window: Window,
+    pub divisor: Divisor,
+    pub technique: Technique,
+    pub metronome: Option<f64>,
+    pub od: Option<f64>,
+    pub chart: Option<String>,
+    pub from: Option<f64>,
+    pub to: Option<f64>,
+    /// List the streams of the chart and ask which one to practice.
+    pub list_streams: bool,
+    pub stream: Option<usize>,
+    pub min_bpm: f64,
+    pub record: Option<String>,
+    pub play: Option<String>,
+    /// Linux input device or recording of one to read the taps from, instead of the terminal.
+    pub evdev: Option<String>,
+    /// Length of a timed session in seconds, counted from the first tap.
+    pub duration: Option<f64>,
+    /// Stream for as long as possible during `duration`, then look at how speed and UR held up.
+    pub stamina: bool,
+    /// Number of taps to make as fast as possible.
+    pub burst: Option<u32>,
+    /// BPM the step test starts at.
+    pub step: Option<f64>,
+    /// Notes in each step of the step test.
+    pub step_notes: Option<usize>,
+    /// BPM of the streams of interval training.
+    pub intervals: Option<f64>,
+    pub rounds: Option<usize>,
+    /// Notes in each stream of interval training.
+    pub round_notes: Option<usize>,
+    /// Seconds of rest between two streams of interval training.
+    pub rest: Option<f64>,
+    /// Start the drills of the training plan.
+    pub training: bool,
+    /// Open the key binding screen instead of practicing.
+    pub rebind: bool,
+}
+
+fn number(arg: &str, value: Option<String>) -> Result<f64, String> {
+    let value = value.ok_or_else(|| format!("Missing value after {}", arg))?;
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err(format!("Invalid value \"{}\" for {}", value, arg)),
+    }
+}
+
+impl Options {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut options = Options::default();
+        options.apply(args)?;
+        Ok(options)
+    }
+
+    /// Applies the given arguments on top of the current options.
+    pub fn apply<I: IntoIterator<Item = String>>(&mut self, args: I) -> Result<(), String> {
+        let mut args = args.into_iter().peekable();
+        if args.peek().map(|a| a == "streams").unwrap_or(false) {
+            args.next();
+            self.list_streams = true;
+            self.chart = Some(args.next().ok_or_else(|| {
+                "Usage: osu_practice streams <beatmap.osu> [--min-bpm <bpm>]".to_string()
+            })?);
+        } else if args.peek().map(|a| a == "keys").unwrap_or(false) {
+            args.next();
+            self.rebind = true;
+        }
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--window" => {
+                    self.window = args.next().unwrap_or_default().parse()?;
+                }
+                "--divisor" => {
+                    self.divisor = args.next().unwrap_or_default().parse()?;
+                }
+                "--technique" => {
+                    self.technique = args.next().unwrap_or_default().parse()?;
+                }
+                "--metronome" => match number(&arg, args.next())? {
+                    bpm if bpm > 0.0 => self.metronome = Some(bpm),
+                    bpm => return Err(format!("Invalid metronome BPM \"{}\"", bpm)),
+                },
+                "--od" => match number(&arg, args.next())? {
+                    od if (0.0..=10.0).contains(&od) => self.od = Some(od),
+                    od => return Err(format!("Overall Difficulty must be between 0 and 10, got \"{}\"", od)),
+                },
+                "--chart" => {
+                    self.chart = Some(args.next().ok_or_else(|| "Missing beatmap after --chart".to_string())?);
+                }
+                "--stream" => match number(&arg, args.next())? {
+                    n if n >= 1.0 && n.fract() == 0.0 => self.stream = Some(n as usize),
+                    n => return Err(format!("Invalid stream number \"{}\"", n)),
+                },
+                "--min-bpm" => self.min_bpm = number(&arg, args.next())?,
+                "--from" => self.from = Some(number(&arg, args.next())?),
+                "--to" => self.to = Some(number(&arg, args.next())?),
+                "--duration" => match number(&arg, args.next())? {
+                    secs if secs > 0.0 && secs <= MAX_DURATION => self.duration = Some(secs),
+                    secs => return Err(format!("Invalid duration \"{}\"", secs)),
+                },
+                "--stamina" => match number(&arg, args.next())? {
+                    secs if secs > 0.0 && secs <= MAX_DURATION => {
+                        self.stamina = true;
+                        self.duration = Some(secs);
+                    }
+                    secs => return Err(format!("Invalid stamina test length \"{}\"", secs)),
+                },
+                "--burst" => match number(&arg, args.next())? {
+                    n if n >= 2.0 && n.fract() == 0.0 => self.burst = Some(n as u32),
+                    n => return Err(format!("Invalid burst length \"{}\"", n)),
+                },
+                "--step" => match number(&arg, args.next())? {
+                    bpm if bpm > 0.0 => self.step = Some(bpm),
+                    bpm => return Err(format!("Invalid step test BPM \"{}\"", bpm)),
+                },
+                "--step-notes" => match number(&arg, args.next())? {
+                    n if n >= 2.0 && n.fract() == 0.0 => self.step_notes = Some(n as usize),
+                    n => return Err(format!("Invalid number of notes per step \"{}\"", n)),
+                },
+                "--intervals" => match number(&arg, args.next())? {
+                    bpm if bpm > 0.0 => self.intervals = Some(bpm),
+                    bpm => return Err(format!("Invalid interval training BPM \"{}\"", bpm)),
+                },
+                "--rounds" => match number(&arg, args.next())? {
+                    n if n >= 1.0 && n.fract() == 0.0 => self.rounds = Some(n as usize),
+                    n => return Err(format!("Invalid number of rounds \"{}\"", n)),
+                },
+                "--round-notes" => match number(&arg, args.next())? {
+                    n if n >= 1.0 && n.fract() == 0.0 => self.round_notes = Some(n as usize),
+                    n => return Err(format!("Invalid number of notes per round \"{}\"", n)),
+                },
+                "--rest" => match number(&arg, args.next())? {
+                    secs if secs >= 0.0 && secs.is_finite() => self.rest = Some(secs),
+                    secs => return Err(format!("Invalid rest \"{}\"", secs)),
+                },
+                "--training" => self.training = true,
+                "--record" => {
+                    self.record = Some(args.next().ok_or_else(|| "Missing file after --record".to_string())?);
+                }
+                "--evdev" => {
+                    self.evdev = Some(args.next().ok_or_else(|| "Missing device after --evdev".to_string())?);
+                }
+                "--play" => {
+                    self.play = Some(args.next().ok_or_else(|| "Missing file after --play".to_string())?);
+                }
+                _ => return Err(format!("Unknown argument \"{}\"\n{}", arg, USAGE)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Options saved with a recording, without the ones that prompt, open another screen or touch files.
+    pub fn recorded<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut options = Options::parse(args)?;
+        options.list_streams = false;
+        options.rebind = false;
+        options.record = None;
+        options.play = None;
+        options.evdev = None;
+        Ok(options)
+    }
+
+    /// Arguments that set up the same practice again, without recording or playback.
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--window".to_string(),
+            self.window.spec(),
+            "--divisor".to_string(),
+            self.divisor.to_string(),
+        ];
+        let mut push = |name: &str, value: String| {
+            args.push(name.to_string());
+            args.push(value);
+        };
+        if self.technique != Technique::Any {
+            push("--technique", self.technique.name().to_string());
+        }
+        if let Some(bpm) = self.metronome {
+            push("--metronome", bpm.to_string());
+        }
+        if let Some(od) = self.od {
+            push("--od", od.to_string());
+        }
+        if let Some(chart) = &self.chart {
+            push("--chart", chart.clone());
+        }
+        if let Some(from) = self.from {
+            push("--from", from.to_string());
+        }
+        if let Some(to) = self.to {
+            push("--to", to.to_string());
+        }
+        if let Some(stream) = self.stream {
+            push("--stream", stream.to_string());
+        }
+        if self.min_bpm > 0.0 {
+            push("--min-bpm", self.min_bpm.to_string());
+        }
+        if let Some(duration) = self.duration {
+            push(if self.stamina { "--stamina" } else { "--duration" }, duration.to_string());
+        }
+        if let Some(taps) = self.burst {
+            push("--burst", taps.to_string());
+        }
+        if let Some(bpm) = self.step {
+            push("--step", bpm.to_string());
+        }
+        if let Some(notes) = self.step_notes {
+            push("--step-notes", notes.to_string());
+        }
+        if let Some(bpm) = self.intervals {
+            push("--intervals", bpm.to_string());
+        }
+        if let Some(rounds) = self.rounds {
+            push("--rounds", rounds.to_string());
+        }
+        if let Some(notes) = self.round_notes {
+            push("--round-notes", notes.to_string());
+        }
+        if let Some(rest) = self.rest {
+            push("--rest", rest.to_string());
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn saved_args_set_up_the_same_practice() {
+        let options = Options::parse(args("streams map.osu --stream 2 --min-bpm 180 --record out.txt")).unwrap();
+        let saved = options.to_args();
+        assert!(!saved.iter().any(|a| a == "streams" || a == "--record"));
+        let replayed = Options::recorded(saved).unwrap();
+        assert_eq!(replayed.chart, Some("map.osu".to_string()));
+        assert_eq!(replayed.stream, Some(2));
+        assert_eq!(replayed.min_bpm, 180.0);
+        assert!(!replayed.list_streams);
+        assert_eq!(replayed.record, None);
+    }
+
+    #[test]
+    fn recorded_args_leave_out_interactive_options() {
+        let replayed = Options::recorded(args("streams map.osu --record out.txt --play in.txt")).unwrap();
+        assert!(!replayed.list_streams);
+        assert_eq!(replayed.chart, Some("map.osu".to_string()));
+        assert_eq!(replayed.record, None);
+        assert_eq!(replayed.play, None);
+        assert!(!Options::recorded(args("keys")).unwrap().rebind);
+    }
+
+    fn rejects(flag: &str, values: &[&str]) {
+        for value in values {
+            assert!(Options::parse(args(&format!("{} {}", flag, value))).is_err(), "{} {}", flag, value);
+        }
+        assert!(Options::parse(args(flag)).is_err(), "{} without a value", flag);
+    }
+
+    #[test]
+    fn metronome() {
+        assert_eq!(Options::parse(args("--metronome 180")).unwrap().metronome, Some(180.0));
+        rejects("--metronome", &["0", "-180", "inf", "NaN", "fast"]);
+    }
+
+    #[test]
+    fn step() {
+        assert_eq!(Options::parse(args("--step 150.5")).unwrap().step, Some(150.5));
+        rejects("--step", &["0", "-150", "inf", "NaN"]);
+    }
+
+    #[test]
+    fn intervals() {
+        assert_eq!(Options::parse(args("--intervals 200")).unwrap().intervals, Some(200.0));
+        rejects("--intervals", &["0", "-200", "inf", "NaN"]);
+    }
+
+    #[test]
+    fn min_bpm() {
+        assert_eq!(Options::parse(args("--min-bpm 160")).unwrap().min_bpm, 160.0);
+        rejects("--min-bpm", &["inf", "-inf", "NaN"]);
+    }
+
+    #[test]
+    fn from() {
+        assert_eq!(Options::parse(args("--from 1500")).unwrap().from, Some(1500.0));
+        rejects("--from", &["inf", "NaN"]);
+    }
+
+    #[test]
+    fn to() {
+        assert_eq!(Options::parse(args("--to 90000")).unwrap().to, Some(90000.0));
+        rejects("--to", &["inf", "NaN"]);
+    }
+
+    #[test]
+    fn rests() {
+        assert_eq!(Options::parse(args("--rest 0")).unwrap().rest, Some(0.0));
+        assert_eq!(Options::parse(args("--rest 2.5")).unwrap().rest, Some(2.5));
+        for rest in &["-1", "NaN", "inf", "soon"] {
+            assert!(Options::parse(args(&format!("--rest {}", rest))).is_err(), "{}", rest);
+        }
+        assert!(Options::parse(args("--rest")).is_err());
+    }
+
+    #[test]
+    fn durations() {
+        let options = Options::parse(args("--duration 90")).unwrap();
+        assert_eq!((options.duration, options.stamina), (Some(90.0), false));
+        let options = Options::parse(args("--stamina 600")).unwrap();
+        assert_eq!((options.duration, options.stamina), (Some(600.0), true));
+        assert_eq!(Options::parse(args("--duration 86400")).unwrap().duration, Some(MAX_DURATION));
+        for flag in &["--duration", "--stamina"] {
+            for secs in &["0", "-5", "86401", "1e30", "inf", "NaN"] {
+                assert!(Options::parse(args(&format!("{} {}", flag, secs))).is_err(), "{} {}", flag, secs);
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use crate::input::Binding;
+use crate::Curses;
+use easycurses::Input;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// How long the thread sleeps between two reads of the terminal.
+const POLL_INTERVAL: Duration = Duration::from_micros(500);
+
+/// What was read from the terminal at once, and when.
+pub type Polled = (Vec<Binding>, Instant);
+
+/// How late taps read from the terminal can be timestamped. A tap that comes right after a read is only seen by the
+/// next one, so each read can be as late as the time since the previous read ended, some of it spent waiting for the
+/// screen to be drawn.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Jitter {
+    pub polls: u32,
+    pub total: Duration,
+    pub max: Duration,
+    /// Longest time a read waited for the terminal.
+    pub wait: Duration,
+}
+
+impl Jitter {
+    fn push(&mut self, late: Duration, waited: Duration) {
+        self.polls += 1;
+        self.total += late;
+        self.max = self.max.max(late);
+        self.wait = self.wait.max(waited);
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.polls == 0 {
+            None
+        } else {
+            Some(self.total / self.polls)
+        }
+    }
+}
+
+impl fmt::Display for Jitter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mean() {
+            Some(mean) => write!(
+                f,
+                "up to {:.2}ms late on average, {:.2}ms at worst, waiting up to {:.2}ms for the screen",
+                mean.as_secs_f64() * 1000.0,
+                self.max.as_secs_f64() * 1000.0,
+                self.wait.as_secs_f64() * 1000.0
+            ),
+            None => write!(f, "not read yet"),
+        }
+    }
+}
+
+/// Mouse buttons pressed in the mouse event curses just reported.
+fn mouse_bindings() -> Vec<Binding> {
+    let masks = [
+        (1, pancurses::BUTTON1_PRESSED),
+        (2, pancurses::BUTTON2_PRESSED),
+        (3, pancurses::BUTTON3_PRESSED),
+    ];
+    match pancurses::getmouse() {
+        Ok(event) => masks
+            .iter()
+            .filter(|(_, mask)| event.bstate & mask != 0)
+            .map(|(button, _)| Binding::Mouse(*button))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn poll(curses: Curses, sender: Sender<Polled>, jitter: Arc<Mutex<Jitter>>, stop: Arc<AtomicBool>) {
+    let mut last_read = None;
+    while !stop.load(Ordering::Relaxed) {
+        let mut read = Vec::new();
+        {
+            // Drawing holds the terminal while it copies a frame over, and taps wait for that too.
+            let asked = Instant::now();
+            let mut curses = curses.lock();
+            let reading = Instant::now();
+            if let Some(last) = last_read {
+                jitter.lock().unwrap().push(reading.duration_since(last), reading.duration_since(asked));
+            }
+            while let Some(input) = curses.get_input() {
+                let time = Instant::now();
+                let bindings = if input == Input::KeyMouse {
+                    mouse_bindings()
+                } else {
+                    vec![Binding::Input(input)]
+                };
+                read.push((bindings, time));
+            }
+            last_read = Some(Instant::now());
+        }
+        for polled in read {
+            if sender.send(polled).is_err() {
+                return;
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Reads the terminal on its own thread, so taps are timestamped when they are read instead of once per frame.
+pub struct InputPoller {
+    receiver: Mutex<Receiver<Polled>>,
+    jitter: Arc<Mutex<Jitter>>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl InputPoller {
+    pub fn spawn(curses: Curses) -> Self {
+        let (sender, receiver) = channel();
+        let jitter = Arc::new(Mutex::new(Jitter::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let jitter = jitter.clone();
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("input".to_string())
+                .spawn(move || poll(curses, sender, jitter, stop))
+                .expect("Failed to start the input thread.")
+        };
+        InputPoller {
+            receiver: Mutex::new(receiver),
+            jitter,
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Everything read since the last call, oldest first.
+    pub fn drain(&self) -> Vec<Polled> {
+        self.receiver.lock().unwrap().try_iter().collect()
+    }
+
+    pub fn jitter(&self) -> Jitter {
+        *self.jitter.lock().unwrap()
+    }
+
+    /// Starts measuring the jitter over again, for a new session.
+    pub fn reset_jitter(&self) {
+        *self.jitter.lock().unwrap() = Jitter::default();
+    }
+}
+
+impl Drop for InputPoller {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter() {
+        let ms = Duration::from_millis;
+        let mut jitter = Jitter::default();
+        assert_eq!(jitter.mean(), None);
+        assert_eq!(jitter.to_string(), "not read yet");
+        jitter.push(ms(1), ms(0));
+        jitter.push(ms(3), ms(2));
+        jitter.push(ms(2), ms(1));
+        assert_eq!(jitter.mean(), Some(ms(2)));
+        assert_eq!((jitter.max, jitter.wait), (ms(3), ms(2)));
+        assert_eq!(
+            jitter.to_string(),
+            "up to 2.00ms late on average, 3.00ms at worst, waiting up to 2.00ms for the screen"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use crate::options::Options;
+use crate::stats::Divisor;
+
+/// Notes per stream offered in the menu.
+pub const ROUND_NOTE_PRESETS: [usize; 3] = [16, 32, 64];
+pub const DEFAULT_ROUND_NOTES: usize = 32;
+/// Seconds from the last note of a stream to the first note of the next.
+pub const DEFAULT_REST: f64 = 10.0;
+pub const DEFAULT_ROUNDS: usize = 8;
+
+/// Interval training: streams of `notes` notes, each followed by a rest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rounds {
+    pub bpm: f64,
+    pub divisor: Divisor,
+    pub notes: usize,
+    pub rest: f64,
+    pub rounds: usize,
+}
+
+/// Where an interval training is at.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Phase {
+    /// Streaming the given round, with the position in beats since its first note.
+    Stream { round: usize, position: f64 },
+    /// Resting after the given round, with the seconds left before the next one.
+    Rest { round: usize, left: f64 },
+    Done,
+}
+
+impl Rounds {
+    /// A drill at the given BPM, shaped by the interval options.
+    pub fn new(bpm: f64, options: &Options) -> Self {
+        Rounds {
+            bpm,
+            divisor: options.divisor,
+            notes: options.round_notes.unwrap_or(DEFAULT_ROUND_NOTES).max(1),
+            rest: options.rest.unwrap_or(DEFAULT_REST).max(0.0),
+            rounds: options.rounds.unwrap_or(DEFAULT_ROUNDS).max(1),
+        }
+    }
+
+    // Milliseconds between two notes of a stream.
+    fn interval(&self) -> f64 {
+        self.divisor.interval(self.bpm) * 1000.0
+    }
+
+    // Milliseconds from the first note of a round to the first note of the next.
+    // Rests shorter than the gap between two notes, down to none at all, play as one more note's gap.
+    fn period(&self) -> f64 {
+        (self.notes - 1) as f64 * self.interval() + (self.rest * 1000.0).max(self.interval())
+    }
+
+    pub fn len(&self) -> usize {
+        self.notes * self.rounds
+    }
+
+    /// Milliseconds from the first beat to the given beat, if there are that many.
+    pub fn beat_time(&self, beat: usize) -> Option<f64> {
+        if beat >= self.len() {
+            return None;
+        }
+        Some((beat / self.notes) as f64 * self.period() + (beat % self.notes) as f64 * self.interval())
+    }
+
+    /// Phase at the given number of milliseconds from the first beat.
+    pub fn phase(&self, time: f64) -> Phase {
+        let round = (time.max(0.0) / self.period()) as usize;
+        let within = time.max(0.0) - round as f64 * self.period();
+        // The last note of a stream still belongs to it until halfway to where the next one would be.
+        let stream = (self.notes as f64 - 0.5) * self.interval();
+        if round >= self.rounds || (round + 1 == self.rounds && within >= stream) {
+            Phase::Done
+        } else if within < stream {
+            Phase::Stream {
+                round,
+                position: within / self.interval(),
+            }
+        } else {
+            Phase::Rest {
+                round,
+                left: (self.period() - within) / 1000.0,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 100ms between notes.
+    fn rounds(notes: usize, rest: f64, rounds: usize) -> Rounds {
+        let options = Options {
+            round_notes: Some(notes),
+            rest: Some(rest),
+            rounds: Some(rounds),
+            ..Default::default()
+        };
+        Rounds::new(150.0, &options)
+    }
+
+    fn assert_phase(phase: Phase, expected: Phase) {
+        let close = match (phase, expected) {
+            (Phase::Stream { round: a, position: p }, Phase::Stream { round: b, position: q }) => {
+                a == b && (p - q).abs() < 1e-9
+            }
+            (Phase::Rest { round: a, left: l }, Phase::Rest { round: b, left: m }) => a == b && (l - m).abs() < 1e-9,
+            (a, b) => a == b,
+        };
+        assert!(close, "{:?} instead of {:?}", phase, expected);
+    }
+
+    #[test]
+    fn beat_times() {
+        let rounds = rounds(4, 1.0, 2);
+        assert_eq!(rounds.len(), 8);
+        let times = (0..9).map(|beat| rounds.beat_time(beat).map(f64::round)).collect::<Vec<_>>();
+        let expected = vec![0.0, 100.0, 200.0, 300.0, 1300.0, 1400.0, 1500.0, 1600.0];
+        assert_eq!(times[..8], expected.into_iter().map(Some).collect::<Vec<_>>()[..]);
+        assert_eq!(times[8], None);
+    }
+
+    #[test]
+    fn phases() {
+        let rounds = rounds(4, 1.0, 2);
+        assert_phase(rounds.phase(-50.0), Phase::Stream { round: 0, position: 0.0 });
+        assert_phase(rounds.phase(150.0), Phase::Stream { round: 0, position: 1.5 });
+        // The last note keeps the stream going for half a note.
+        assert_phase(rounds.phase(349.0), Phase::Stream { round: 0, position: 3.49 });
+        assert_phase(rounds.phase(350.0), Phase::Rest { round: 0, left: 0.95 });
+        assert_phase(rounds.phase(1299.0), Phase::Rest { round: 0, left: 0.001 });
+        assert_phase(rounds.phase(1300.0), Phase::Stream { round: 1, position: 0.0 });
+        assert_phase(rounds.phase(1649.0), Phase::Stream { round: 1, position: 3.49 });
+        assert_phase(rounds.phase(1650.0), Phase::Done);
+        assert_phase(rounds.phase(1e9), Phase::Done);
+    }
+
+    #[test]
+    fn no_rest() {
+        // Single notes without rests still move on by one note every time.
+        let rounds = rounds(1, 0.0, 3);
+        assert_eq!(rounds.beat_time(2).map(f64::round), Some(200.0));
+        assert_phase(rounds.phase(0.0), Phase::Stream { round: 0, position: 0.0 });
+        assert_phase(rounds.phase(50.0), Phase::Rest { round: 0, left: 0.05 });
+        assert_phase(rounds.phase(100.0), Phase::Stream { round: 1, position: 0.0 });
+        assert_phase(rounds.phase(250.0), Phase::Done);
+    }
+
+    #[test]
+    fn negative_rest() {
+        let rounds = rounds(4, -5.0, 2);
+        assert_eq!(rounds.rest, 0.0);
+        assert_eq!(rounds.beat_time(4).map(f64::round), Some(400.0));
+        assert_phase(rounds.phase(400.0), Phase::Stream { round: 1, position: 0.0 });
+    }
+}
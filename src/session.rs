@@ -0,0 +1,374 @@
+use crate::input::{InputEvent, Key};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const HEADER: &str = "osu_practice session 1";
+
+#[derive(Debug)]
+pub enum SessionError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Io(e) => write!(f, "Failed to access session file: {}", e),
+            SessionError::Parse { line, message } => {
+                write!(f, "Invalid session file at line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<std::io::Error> for SessionError {
+    fn from(e: std::io::Error) -> Self {
+        SessionError::Io(e)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SessionEvent {
+    /// Time since the recording started.
+    pub offset: Duration,
+    pub key: Key,
+    /// Whether the key went down or, with input backends that see it, up.
+    pub pressed: bool,
+}
+
+/// A recorded session: the options it was played with, every tap and every release.
+///
+/// The file is line based:
+/// ```text
+/// osu_practice session 1
+/// arg --metronome
+/// arg 180
+/// tap 0 left
+/// release 41200 left
+/// tap 83412 right
+/// ```
+/// where offsets are in microseconds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Session {
+    pub args: Vec<String>,
+    pub events: Vec<SessionEvent>,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> Result<Self, SessionError> {
+        Session::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, SessionError> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, line)) if line.trim() == HEADER => {}
+            _ => {
+                return Err(SessionError::Parse {
+                    line: 1,
+                    message: format!("missing \"{}\" header", HEADER),
+                })
+            }
+        }
+        let mut session = Session::default();
+        let mut previous = Duration::from_secs(0);
+        for (i, line) in lines {
+            let line_no = i + 1;
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(2, ' ');
+            match (parts.next(), parts.next()) {
+                (Some("arg"), Some(arg)) => session.args.push(arg.to_string()),
+                (Some(kind @ "tap"), Some(rest)) | (Some(kind @ "release"), Some(rest)) => {
+                    let mut fields = rest.split_whitespace();
+                    let offset = fields
+                        .next()
+                        .and_then(|f| f.parse::<u64>().ok())
+                        .ok_or_else(|| SessionError::Parse {
+                            line: line_no,
+                            message: format!("{} needs a time in microseconds", kind),
+                        })?;
+                    let offset = Duration::from_micros(offset);
+                    if offset < previous {
+                        return Err(SessionError::Parse {
+                            line: line_no,
+                            message: format!("{} is earlier than the event before it", kind),
+                        });
+                    }
+                    previous = offset;
+                    let name = fields.next().unwrap_or("");
+                    let key = Key::from_name(name).ok_or_else(|| SessionError::Parse {
+                        line: line_no,
+                        message: format!("unknown key \"{}\"", name),
+                    })?;
+                    session.events.push(SessionEvent {
+                        offset,
+                        key,
+                        pressed: kind == "tap",
+                    });
+                }
+                _ => {
+                    return Err(SessionError::Parse {
+                        line: line_no,
+                        message: format!("unexpected \"{}\"", line),
+                    })
+                }
+            }
+        }
+        Ok(session)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.events
+            .last()
+            .map(|e| e.offset)
+            .unwrap_or_else(|| Duration::from_secs(0))
+    }
+}
+
+/// File the given attempt of a recording to `path` goes to, numbered from the second one on
+/// (`out.txt`, `out-2.txt`, `out-3.txt`...) so that starting over keeps the earlier attempts.
+pub fn attempt_path(path: &str, attempt: u32) -> String {
+    if attempt <= 1 {
+        return path.to_string();
+    }
+    let path = Path::new(path);
+    let stem = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, attempt, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, attempt),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Writes taps and releases to a session file as they happen.
+pub struct Recorder {
+    pub path: String,
+    pub error: Option<String>,
+    start: Instant,
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    /// Starts a recording at `start`, the time taps are counted from.
+    pub fn create(path: &str, args: &[String], start: Instant) -> Result<Self, SessionError> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "{}", HEADER)?;
+        for arg in args {
+            writeln!(out, "arg {}", arg)?;
+        }
+        out.flush()?;
+        Ok(Recorder {
+            path: path.to_string(),
+            error: None,
+            start,
+            out,
+        })
+    }
+
+    pub fn record(&mut self, key: Key, time: Instant) {
+        self.write("tap", key, time);
+    }
+
+    pub fn release(&mut self, key: Key, time: Instant) {
+        self.write("release", key, time);
+    }
+
+    fn write(&mut self, kind: &str, key: Key, time: Instant) {
+        if self.error.is_some() {
+            return;
+        }
+        let offset = time.duration_since(self.start).as_micros();
+        if let Err(e) = writeln!(self.out, "{} {} {}", kind, offset, key.name()) {
+            self.error = Some(e.to_string());
+        }
+    }
+
+    /// Leaves the time spent paused out of the recording.
+    pub fn delay(&mut self, by: Duration) {
+        self.start += by;
+    }
+
+    pub fn flush(&mut self) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(e) = self.out.flush() {
+            self.error = Some(e.to_string());
+        }
+    }
+}
+
+/// Feeds the taps and releases of a recorded session back at the pace they were made.
+pub struct Player {
+    pub path: String,
+    session: Session,
+    start: Option<Instant>,
+    next: usize,
+}
+
+impl Player {
+    pub fn new(path: &str, session: Session) -> Self {
+        Player {
+            path: path.to_string(),
+            session,
+            start: None,
+            next: 0,
+        }
+    }
+
+    /// Events that are due at `now`, with the exact time they were made at relative to the start of the playback.
+    pub fn poll(&mut self, now: Instant) -> Vec<InputEvent> {
+        let start = *self.start.get_or_insert(now);
+        let mut due = Vec::new();
+        while let Some(event) = self.session.events.get(self.next) {
+            let time = start + event.offset;
+            if time > now {
+                break;
+            }
+            due.push(if event.pressed {
+                InputEvent::Input(event.key, time)
+            } else {
+                InputEvent::Release(event.key, time)
+            });
+            self.next += 1;
+        }
+        due
+    }
+
+    /// Holds the remaining taps back by the time spent paused.
+    pub fn delay(&mut self, by: Duration) {
+        if let Some(start) = self.start.as_mut() {
+            *start += by;
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.session.events.len()
+    }
+
+    /// Fraction of the taps played so far.
+    pub fn progress(&self) -> f64 {
+        if self.session.events.is_empty() {
+            1.0
+        } else {
+            self.next as f64 / self.session.events.len() as f64
+        }
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(text: &str) -> (usize, String) {
+        match Session::parse(text) {
+            Err(SessionError::Parse { line, message }) => (line, message),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join(format!("osu_practice_session_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        let args = vec!["--metronome".to_string(), "180".to_string()];
+        let start = Instant::now();
+        let mut recorder = Recorder::create(path, &args, start).unwrap();
+        recorder.record(Key::Left, start);
+        recorder.release(Key::Left, start + Duration::from_micros(41200));
+        recorder.record(Key::Right, start + Duration::from_micros(83412));
+        // The pause is cut out of the offsets.
+        recorder.delay(Duration::from_secs(2));
+        recorder.record(Key::Extra, start + Duration::from_micros(2_166_824));
+        recorder.flush();
+        assert_eq!(recorder.error, None);
+
+        let session = Session::load(Path::new(path)).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(session.args, args);
+        let events = session
+            .events
+            .iter()
+            .map(|e| (e.offset.as_micros(), e.key, e.pressed))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                (0, Key::Left, true),
+                (41200, Key::Left, false),
+                (83412, Key::Right, true),
+                (166_824, Key::Extra, true),
+            ]
+        );
+        assert_eq!(session.duration(), Duration::from_micros(166_824));
+    }
+
+    #[test]
+    fn play_back_at_the_recorded_pace() {
+        let session =
+            Session::parse("osu_practice session 1\ntap 0 left\ntap 1000 right\nrelease 1500 left\ntap 5000 left\n")
+                .unwrap();
+        let mut player = Player::new("test", session);
+        let start = Instant::now();
+        let at = |micros| start + Duration::from_micros(micros);
+        assert_eq!(player.poll(start), vec![InputEvent::Input(Key::Left, start)]);
+        assert_eq!(
+            player.poll(at(2000)),
+            vec![InputEvent::Input(Key::Right, at(1000)), InputEvent::Release(Key::Left, at(1500))]
+        );
+        assert!(!player.is_finished());
+        player.delay(Duration::from_micros(10000));
+        assert_eq!(player.poll(at(6000)), vec![]);
+        assert_eq!(player.poll(at(15000)), vec![InputEvent::Input(Key::Left, at(15000))]);
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn bad_header() {
+        assert_eq!(parse_error("").0, 1);
+        assert_eq!(parse_error("osu_practice session 2\ntap 0 left\n").0, 1);
+        assert_eq!(parse_error("tap 0 left\n").0, 1);
+    }
+
+    #[test]
+    fn bad_taps() {
+        assert_eq!(parse_error("osu_practice session 1\ntap soon left\n").0, 2);
+        assert_eq!(parse_error("osu_practice session 1\ntap -5 left\n").0, 2);
+        assert_eq!(parse_error("osu_practice session 1\n\ntap 0 thumb\n"), (3, "unknown key \"thumb\"".to_string()));
+        assert_eq!(parse_error("osu_practice session 1\nclick 0 left\n").0, 2);
+        assert_eq!(
+            parse_error("osu_practice session 1\nrelease later left\n"),
+            (2, "release needs a time in microseconds".to_string())
+        );
+    }
+
+    #[test]
+    fn out_of_order_taps() {
+        let (line, _) = parse_error("osu_practice session 1\ntap 0 left\ntap 2000 right\ntap 1000 left\n");
+        assert_eq!(line, 4);
+        let (line, message) = parse_error("osu_practice session 1\ntap 2000 left\nrelease 1000 left\n");
+        assert_eq!((line, message.as_str()), (3, "release is earlier than the event before it"));
+        // Taps at the same time are fine.
+        assert!(Session::parse("osu_practice session 1\ntap 1000 left\ntap 1000 right\n").is_ok());
+    }
+
+    #[test]
+    fn attempts_get_their_own_file() {
+        assert_eq!(attempt_path("out.txt", 1), "out.txt");
+        assert_eq!(attempt_path("out.txt", 2), "out-2.txt");
+        assert_eq!(attempt_path("sessions/out", 3), "sessions/out-3");
+        assert_eq!(attempt_path("sessions/v1.2/run.session.txt", 12), "sessions/v1.2/run.session-12.txt");
+    }
+}
@@ -0,0 +1,165 @@
+use crate::stats::{Divisor, IntervalStats, Stats};
+
+/// Test lengths in seconds offered in the menu.
+pub const STAMINA_PRESETS: [f64; 3] = [10.0, 30.0, 60.0];
+pub const DEFAULT_STAMINA: f64 = 30.0;
+
+/// Equal slices of the test the BPM is reported for.
+pub const STAMINA_SEGMENTS: usize = 8;
+
+// Intervals the UR is computed over while looking for the point it blew up.
+const UR_WINDOW: usize = 16;
+// How many times the UR of the first quarter counts as blown up.
+const UR_BLOWUP_FACTOR: f64 = 2.0;
+
+/// How the speed and consistency held up over a stamina test.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StaminaReport {
+    /// Seconds from the first to the last tap.
+    pub lasted: f64,
+    pub segment_bpms: Vec<Option<f64>>,
+    pub first_quarter_bpm: Option<f64>,
+    pub last_quarter_bpm: Option<f64>,
+    /// UR of the first quarter, what the rest is compared to.
+    pub baseline_ur: Option<f64>,
+    /// Seconds into the test and UR of the first window of taps that went past
+    /// `UR_BLOWUP_FACTOR` times the baseline.
+    pub blowup: Option<(f64, f64)>,
+}
+
+fn mean_bpm<'a, I: Iterator<Item = &'a f64>>(intervals: I, divisor: Divisor) -> Option<f64> {
+    let (count, sum) = intervals.fold((0, 0.0), |(count, sum), i| (count + 1, sum + i));
+    if count == 0 {
+        None
+    } else {
+        Some(divisor.bpm(1000.0 / (sum / count as f64)))
+    }
+}
+
+impl StaminaReport {
+    pub fn new(stats: &Stats, divisor: Divisor) -> Self {
+        let times = &stats.interval_times;
+        let intervals = &stats.session_intervals;
+        let lasted = times.last().copied().unwrap_or(0.0);
+        let quarter = |from: f64, to: f64| {
+            times
+                .iter()
+                .zip(intervals.iter())
+                .filter(move |(t, _)| **t >= from * lasted && **t <= to * lasted)
+                .map(|(_, i)| i)
+        };
+        let baseline_ur = IntervalStats::from_intervals(&quarter(0.0, 0.25).cloned().collect::<Vec<_>>())
+            .map(|i| i.unstable_rate);
+        let blowup = baseline_ur.and_then(|baseline| {
+            intervals
+                .windows(UR_WINDOW)
+                .zip(times.iter().skip(UR_WINDOW - 1))
+                .filter_map(|(window, time)| {
+                    IntervalStats::from_intervals(window).map(|i| (*time, i.unstable_rate))
+                })
+                .find(|(_, ur)| *ur > baseline * UR_BLOWUP_FACTOR)
+        });
+        StaminaReport {
+            lasted,
+            segment_bpms: stats.bpm_timeline(divisor, STAMINA_SEGMENTS),
+            first_quarter_bpm: mean_bpm(quarter(0.0, 0.25), divisor),
+            last_quarter_bpm: mean_bpm(quarter(0.75, 1.0), divisor),
+            baseline_ur,
+            blowup,
+        }
+    }
+
+    /// How much slower the last quarter was than the first, from 0.0 to 1.0. Negative if it got faster.
+    pub fn speed_drop(&self) -> Option<f64> {
+        match (self.first_quarter_bpm, self.last_quarter_bpm) {
+            (Some(first), Some(last)) if first > 0.0 => Some((first - last) / first),
+            _ => None,
+        }
+    }
+
+    /// Seconds covered by each of `segment_bpms`.
+    pub fn segment_length(&self) -> f64 {
+        self.lasted / STAMINA_SEGMENTS as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Stats of a stream tapped at the given intervals in milliseconds.
+    fn stats(intervals: &[f64]) -> Stats {
+        let mut stats = Stats::default();
+        let mut time = 0.0;
+        for interval in intervals {
+            time += interval / 1000.0;
+            stats.session_intervals.push(*interval);
+            stats.interval_times.push(time);
+        }
+        stats
+    }
+
+    // Taps alternating `spread` milliseconds either side of the given intervals.
+    fn wobble(intervals: impl Iterator<Item = f64>, spread: f64) -> Vec<f64> {
+        intervals
+            .enumerate()
+            .map(|(i, interval)| if i % 2 == 0 { interval - spread } else { interval + spread })
+            .collect()
+    }
+
+    #[test]
+    fn steady_stream() {
+        let report = StaminaReport::new(&stats(&wobble((0..200).map(|_| 100.0), 2.0)), Divisor::default());
+        assert!((report.lasted - 20.0).abs() < 1e-9);
+        assert!((report.segment_length() - 2.5).abs() < 1e-9);
+        assert_eq!(report.segment_bpms.len(), STAMINA_SEGMENTS);
+        for bpm in &report.segment_bpms {
+            assert!((bpm.unwrap() - 150.0).abs() < 1.0);
+        }
+        assert!(report.speed_drop().unwrap().abs() < 0.01);
+        assert!((report.baseline_ur.unwrap() - 20.0).abs() < 0.5);
+        assert_eq!(report.blowup, None);
+    }
+
+    #[test]
+    fn slowing_down() {
+        // From 100ms to 120ms between taps, without getting any less even.
+        let report = StaminaReport::new(
+            &stats(&wobble((0..200).map(|i| 100.0 + i as f64 * 0.1), 2.0)),
+            Divisor::default(),
+        );
+        let (first, last) = (report.first_quarter_bpm.unwrap(), report.last_quarter_bpm.unwrap());
+        assert!(first > 145.0 && last < 130.0);
+        assert!(report.speed_drop().unwrap() > 0.1);
+        assert_eq!(report.blowup, None);
+
+        // Speeding up is a negative drop.
+        let intervals = [120.0; 40].iter().chain(&[100.0; 40]).copied().collect::<Vec<_>>();
+        let report = StaminaReport::new(&stats(&intervals), Divisor::default());
+        assert!((report.speed_drop().unwrap() + 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn blowing_up() {
+        // Same speed all along, but 20ms off either way after ten seconds.
+        let intervals = wobble((0..100).map(|_| 100.0), 2.0)
+            .into_iter()
+            .chain(wobble((0..100).map(|_| 100.0), 20.0))
+            .collect::<Vec<_>>();
+        let report = StaminaReport::new(&stats(&intervals), Divisor::default());
+        assert!(report.speed_drop().unwrap().abs() < 0.01);
+        let (time, ur) = report.blowup.unwrap();
+        // Found with the first window that has an uneven tap.
+        assert!((time - 10.08).abs() < 1e-9);
+        assert!(ur > report.baseline_ur.unwrap() * UR_BLOWUP_FACTOR);
+    }
+
+    #[test]
+    fn no_taps() {
+        let report = StaminaReport::new(&Stats::default(), Divisor::default());
+        assert_eq!(report.lasted, 0.0);
+        assert_eq!(report.segment_bpms, vec![None; STAMINA_SEGMENTS]);
+        assert_eq!(report.speed_drop(), None);
+        assert_eq!((report.baseline_ur, report.blowup), (None, None));
+    }
+}
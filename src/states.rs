@@ -0,0 +1,1263 @@
+use crate::burst::*;
+use crate::clock::*;
+use crate::drift::*;
+use crate::evdev::*;
+use crate::history::*;
+use crate::input::*;
+use crate::judge::*;
+use crate::options::{Options, MAX_DURATION};
+use crate::poller::*;
+use crate::rounds::*;
+use crate::session::*;
+use crate::stamina::*;
+use crate::stats::*;
+use crate::step::*;
+use crate::training::*;
+use crate::{
+    draw_bpm_graph, draw_drift_graph, draw_interval_histogram, Curses, Paused, Warnings, BPM_GRAPH_COLUMNS,
+    COLOR_NORMAL, COLOR_TITLE, DEFAULT_OD, DRIFT_GRAPH_HALF, GRAPH_HEIGHT, HISTOGRAM_BINS, KEYMAP_PATH,
+};
+use amethyst::ecs::*;
+use amethyst::prelude::*;
+use amethyst::shrev::{EventChannel, ReaderId};
+use amethyst::utils::application_root_dir;
+use easycurses::*;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Which state is on top, for the systems that only run in some of them.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Screen {
+    Menu,
+    Playing,
+    Paused,
+    Results,
+    Keys,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Screen::Menu
+    }
+}
+
+/// When the current session ends on its own.
+#[derive(Default)]
+pub struct SessionLimit {
+    pub duration: Option<Duration>,
+    pub taps: Option<u32>,
+}
+
+/// Sessions recorded since the program started, so each attempt gets its own file.
+#[derive(Default)]
+pub struct RecordedAttempts(pub u32);
+
+/// Everything needed to start a practice session, and to start it again.
+#[derive(Clone)]
+pub struct Practice {
+    pub options: Options,
+    pub metronome: Option<Metronome>,
+    /// Path and content of the session to play back.
+    pub replay: Option<(String, Session)>,
+    /// The drill being played, when following the training plan.
+    pub training: Option<Training>,
+}
+
+impl Practice {
+    pub fn od(&self) -> f64 {
+        self.metronome
+            .as_ref()
+            .map(|m| m.od)
+            .unwrap_or_else(|| self.options.od.unwrap_or(DEFAULT_OD))
+    }
+
+    /// A timed metronome session for a drill of the training plan.
+    fn drill(&self, training: Training) -> Practice {
+        let mut practice = self.clone();
+        let options = &mut practice.options;
+        options.metronome = Some(training.bpm());
+        options.duration = Some(training.drill().duration);
+        options.stamina = false;
+        options.burst = None;
+        options.step = None;
+        options.chart = None;
+        practice.metronome = Some(Metronome::fixed(
+            training.bpm(),
+            options.divisor,
+            options.od.unwrap_or(DEFAULT_OD),
+        ));
+        practice.training = Some(training);
+        practice
+    }
+}
+
+/// Input events sent since the state last looked.
+fn read_events(world: &World, reader: &mut Option<ReaderId<InputEvent>>) -> Vec<InputEvent> {
+    let mut channel = world.write_resource::<EventChannel<InputEvent>>();
+    let reader = reader.get_or_insert_with(|| channel.register_reader());
+    channel.read(reader).copied().collect()
+}
+
+fn set_screen(world: &World, screen: Screen) {
+    *world.write_resource::<Screen>() = screen;
+}
+
+/// Shows a warning under the menu and the session, once.
+fn warn(world: &World, warning: String) {
+    let mut warnings = world.write_resource::<Warnings>();
+    if !warnings.0.contains(&warning) {
+        warnings.0.push(warning);
+    }
+}
+
+fn start_curses() -> EasyCurses {
+    let mut curses = EasyCurses::initialize_system().expect("Failed to start ncurses.");
+    curses.set_input_mode(InputMode::Character);
+    curses.set_keypad_enabled(true);
+    curses.set_echo(false);
+    curses.set_cursor_visibility(CursorVisibility::Invisible);
+    curses.set_input_timeout(TimeoutMode::Immediate);
+    #[cfg(unix)]
+    unsafe{ ncurses::ll::set_escdelay(0) };
+    curses
+}
+
+/// Mouse buttons only show up as input once asked for. Takes the locked terminal, as every ncurses call has to.
+fn enable_mouse(_curses: &mut EasyCurses) {
+    pancurses::mousemask(pancurses::ALL_MOUSE_EVENTS, std::ptr::null_mut());
+    pancurses::mouseinterval(0);
+}
+
+fn keymap_path() -> Result<PathBuf, String> {
+    application_root_dir()
+        .map(|root| root.join(KEYMAP_PATH))
+        .map_err(|e| format!("Failed to find input.yml: {}", e))
+}
+
+/// The bindings of input.yml, or the defaults if they can't be loaded.
+fn load_keymap(warnings: &mut Vec<String>) -> Keymap {
+    let loaded = keymap_path().and_then(|path| Keymap::load(&path).map_err(|e| e.to_string()));
+    loaded.unwrap_or_else(|e| {
+        warnings.push(format!("{}. Using the default keys.", e));
+        Keymap::default()
+    })
+}
+
+fn save_keymap(keymap: &Keymap) -> String {
+    match keymap_path() {
+        Ok(path) => match keymap.save(&path) {
+            Ok(()) => format!("Saved to {}", path.display()),
+            Err(e) => e.to_string(),
+        },
+        Err(e) => e,
+    }
+}
+
+/// Name of the practice mode, as stored in the history.
+fn mode_name(options: &Options, metronome: Option<&Metronome>) -> &'static str {
+    match metronome {
+        None if options.stamina => "stamina",
+        None if options.burst.is_some() => "burst",
+        None => "free",
+        Some(metronome) => match metronome.pattern {
+            Pattern::Fixed { .. } => "metronome",
+            Pattern::Chart(_) if metronome.looping => "stream",
+            Pattern::Chart(_) => "chart",
+            Pattern::Ramp(_) => "step",
+            Pattern::Rounds(_) => "intervals",
+        },
+    }
+}
+
+/// Tells about the lines of the history that couldn't be read.
+fn skipped_lines(history: &History) -> Option<String> {
+    let first = history.skipped.first()?;
+    Some(match history.skipped.len() {
+        1 => format!("{}, skipped it", first),
+        n => format!("{}, skipped it and {} more", first, n - 1),
+    })
+}
+
+/// Adds the session to the history, and tells how it compares to the personal bests.
+fn save_session(summary: &SessionSummary) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut history = match History::load_default() {
+        Ok(history) => history,
+        // Still saved when the rest of the history can't be read.
+        Err(e) => match History::default_path() {
+            Ok(path) => {
+                messages.push(e.to_string());
+                History {
+                    path,
+                    ..Default::default()
+                }
+            }
+            Err(e) => return vec![e.to_string()],
+        },
+    };
+    messages.extend(skipped_lines(&history));
+    let bucket = summary.bpm_bucket();
+    let bucket_name = bucket
+        .map(|b| format!("{} at {}-{} BPM", summary.mode, b, b + BPM_BUCKET_SIZE - 1))
+        .unwrap_or_else(|| summary.mode.clone());
+    match history.best(&summary.mode, bucket) {
+        Some(best) => {
+            let records = best.beaten_by(summary);
+            if !records.is_empty() {
+                let names = records.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+                messages.push(format!("New personal best for {}: {}!", bucket_name, names.join(", ")));
+            }
+        }
+        None => messages.push(format!("First session for {}.", bucket_name)),
+    }
+    if let Err(e) = history.append(summary.clone()) {
+        messages.push(e.to_string());
+    }
+    messages
+}
+
+/// Moves the target of the training plan according to the history, once all the drills are done.
+fn review_plan(training: Training) -> Vec<String> {
+    let history = match History::load_default() {
+        Ok(history) => history,
+        Err(e) => return vec![e.to_string()],
+    };
+    let plan = TrainingPlan {
+        target_bpm: training.target_bpm,
+    };
+    let review = plan.review(&history);
+    // Skipped lines were already reported when the session was saved.
+    let mut messages = Vec::new();
+    if let (Some(accuracy), Some(ur)) = (review.accuracy, review.unstable_rate) {
+        messages.push(format!(
+            "Last {} sessions at {} BPM: {:.2}% accuracy, {:.1} UR on average",
+            review.sessions,
+            plan.target_bpm,
+            accuracy * 100.0,
+            ur
+        ));
+    }
+    messages.push(if review.target_bpm > plan.target_bpm {
+        format!("Target raised to {} BPM for the next session.", review.target_bpm)
+    } else if review.target_bpm < plan.target_bpm {
+        format!("Target lowered to {} BPM for the next session.", review.target_bpm)
+    } else {
+        format!("Target stays at {} BPM for the next session.", review.target_bpm)
+    });
+    let next = TrainingPlan {
+        target_bpm: review.target_bpm,
+    };
+    if let Err(e) = next.save_default() {
+        messages.push(e.to_string());
+    }
+    messages
+}
+
+/// Names of the keys bound to a slot, for the help lines.
+fn key_names(keymap: &Keymap, slot: Slot) -> String {
+    let names = keymap.names(slot);
+    if names.is_empty() {
+        "unbound".to_string()
+    } else {
+        names.join("/")
+    }
+}
+
+/// Shows the results of the session, or goes back to the menu if nothing was played.
+fn end_session(world: &World, practice: &Practice) -> SimpleTrans {
+    if world.read_resource::<Stats>().total == 0 {
+        Trans::Pop
+    } else {
+        Trans::Switch(Box::new(ResultsState::new(practice.clone())))
+    }
+}
+
+/// What to open right away when the menu starts.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Launch {
+    Menu,
+    Practice,
+    Keys,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Mode {
+    Free,
+    Metronome,
+    Stamina,
+    Burst,
+    Step,
+    Intervals,
+    Training,
+    Chart,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum MenuItem {
+    Mode,
+    Bpm,
+    Length,
+    Technique,
+    Start,
+    Keys,
+    Quit,
+}
+
+impl MenuItem {
+    const ALL: [MenuItem; 7] = [
+        MenuItem::Mode,
+        MenuItem::Bpm,
+        MenuItem::Length,
+        MenuItem::Technique,
+        MenuItem::Start,
+        MenuItem::Keys,
+        MenuItem::Quit,
+    ];
+}
+
+const DEFAULT_BPM: f64 = 180.0;
+const BPM_STEP: f64 = 5.0;
+// Seconds added or removed from the duration with each press.
+const DURATION_STEP: f64 = 30.0;
+
+/// The preset `step` places away from `current`, or the first or last one if `current` isn't a preset.
+fn cycle<T: Copy + PartialEq>(presets: &[T], current: Option<T>, step: i32) -> T {
+    let count = presets.len() as i32;
+    let i = match presets.iter().position(|p| Some(*p) == current) {
+        Some(i) => (i as i32 + step).rem_euclid(count),
+        // Custom values from the command line go back to the presets.
+        None if step > 0 => 0,
+        None => count - 1,
+    };
+    presets[i as usize]
+}
+
+/// The main menu: picks the mode, BPM and length of the next session.
+/// Owns the terminal for the whole run of the application.
+pub struct MenuState {
+    practice: Practice,
+    /// The chart given on the command line, if any.
+    chart: Option<Metronome>,
+    launch: Launch,
+    mode: Mode,
+    bpm: f64,
+    duration: Option<f64>,
+    burst: u32,
+    step_notes: usize,
+    round_notes: usize,
+    plan: TrainingPlan,
+    selected: usize,
+    redraw: bool,
+    reader: Option<ReaderId<InputEvent>>,
+}
+
+impl MenuState {
+    pub fn new(practice: Practice, launch: Launch) -> Self {
+        let chart = practice
+            .metronome
+            .clone()
+            .filter(|m| m.pattern.len().is_some());
+        let mode = if practice.options.training {
+            Mode::Training
+        } else if chart.is_some() {
+            Mode::Chart
+        } else if practice.options.stamina {
+            Mode::Stamina
+        } else if practice.options.burst.is_some() {
+            Mode::Burst
+        } else if practice.options.step.is_some() {
+            Mode::Step
+        } else if practice.options.intervals.is_some() {
+            Mode::Intervals
+        } else if practice.metronome.is_some() {
+            Mode::Metronome
+        } else {
+            Mode::Free
+        };
+        MenuState {
+            bpm: practice
+                .options
+                .metronome
+                .or(practice.options.step)
+                .or(practice.options.intervals)
+                .unwrap_or(DEFAULT_BPM),
+            duration: practice.options.duration,
+            burst: practice.options.burst.unwrap_or(DEFAULT_BURST),
+            step_notes: practice.options.step_notes.unwrap_or(DEFAULT_STEP_NOTES),
+            round_notes: practice.options.round_notes.unwrap_or(DEFAULT_ROUND_NOTES),
+            plan: TrainingPlan::default(),
+            chart,
+            practice,
+            launch,
+            mode,
+            selected: MenuItem::ALL.iter().position(|i| *i == MenuItem::Start).unwrap(),
+            redraw: true,
+            reader: None,
+        }
+    }
+
+    fn modes(&self) -> Vec<Mode> {
+        let mut modes = vec![Mode::Free, Mode::Metronome, Mode::Stamina, Mode::Burst, Mode::Step, Mode::Intervals, Mode::Training];
+        if self.chart.is_some() {
+            modes.push(Mode::Chart);
+        }
+        modes
+    }
+
+    /// The practice the menu is set up for.
+    fn practice(&self) -> Practice {
+        if self.mode == Mode::Training {
+            return self.practice.drill(Training::new(self.plan.target_bpm));
+        }
+        let mut practice = self.practice.clone();
+        let options = &mut practice.options;
+        options.duration = self.duration;
+        options.stamina = self.mode == Mode::Stamina;
+        options.burst = None;
+        options.step = None;
+        options.intervals = None;
+        match self.mode {
+            Mode::Burst => {
+                options.burst = Some(self.burst);
+                options.duration = None;
+                options.metronome = None;
+                options.chart = None;
+                practice.metronome = None;
+            }
+            Mode::Step => {
+                options.step = Some(self.bpm);
+                options.step_notes = Some(self.step_notes);
+                options.duration = None;
+                options.metronome = None;
+                options.chart = None;
+                practice.metronome = Some(Metronome::new(
+                    Pattern::Ramp(Ramp::new(self.bpm, self.step_notes, options.divisor)),
+                    options.od.unwrap_or(DEFAULT_OD),
+                ));
+            }
+            Mode::Intervals => {
+                options.intervals = Some(self.bpm);
+                options.round_notes = Some(self.round_notes);
+                options.duration = None;
+                options.metronome = None;
+                options.chart = None;
+                practice.metronome = Some(Metronome::new(
+                    Pattern::Rounds(Rounds::new(self.bpm, options)),
+                    options.od.unwrap_or(DEFAULT_OD),
+                ));
+            }
+            Mode::Free | Mode::Stamina => {
+                options.metronome = None;
+                options.chart = None;
+                practice.metronome = None;
+            }
+            Mode::Metronome => {
+                options.metronome = Some(self.bpm);
+                options.chart = None;
+                practice.metronome = Some(Metronome::fixed(
+                    self.bpm,
+                    options.divisor,
+                    options.od.unwrap_or(DEFAULT_OD),
+                ));
+            }
+            Mode::Chart => practice.metronome = self.chart.clone(),
+            Mode::Training => {}
+        }
+        practice
+    }
+
+    /// Picks up the target of the last training session.
+    fn load_plan(&mut self, world: &World) {
+        match TrainingPlan::load_default() {
+            Ok(plan) => self.plan = plan,
+            Err(e) => warn(world, e.to_string()),
+        }
+    }
+
+    /// Starts the session the menu is set up for.
+    fn start(&self, world: &World) -> SimpleTrans {
+        if self.mode == Mode::Training {
+            // Keeps the target if it was changed in the menu.
+            if let Err(e) = self.plan.save_default() {
+                warn(world, e.to_string());
+            }
+        }
+        Trans::Push(Box::new(PlayingState::new(self.practice())))
+    }
+
+    fn change(&mut self, step: i32) {
+        match MenuItem::ALL[self.selected] {
+            MenuItem::Mode => {
+                let modes = self.modes();
+                let i = modes.iter().position(|m| *m == self.mode).unwrap_or(0) as i32;
+                self.mode = modes[(i + step).rem_euclid(modes.len() as i32) as usize];
+                // Stamina tests always have an end.
+                if self.mode == Mode::Stamina && self.duration.is_none() {
+                    self.duration = Some(DEFAULT_STAMINA);
+                }
+            }
+            MenuItem::Technique => {
+                let technique = &mut self.practice.options.technique;
+                *technique = cycle(&Technique::ALL, Some(*technique), step);
+            }
+            MenuItem::Bpm if self.mode == Mode::Training => {
+                self.plan.target_bpm = (self.plan.target_bpm + BPM_STEP * step as f64).max(BPM_STEP);
+            }
+            MenuItem::Bpm => self.bpm = (self.bpm + BPM_STEP * step as f64).max(BPM_STEP),
+            MenuItem::Length if self.mode == Mode::Burst => {
+                self.burst = cycle(&BURST_PRESETS, Some(self.burst), step);
+            }
+            MenuItem::Length if self.mode == Mode::Intervals => {
+                self.round_notes = cycle(&ROUND_NOTE_PRESETS, Some(self.round_notes), step);
+            }
+            MenuItem::Length if self.mode == Mode::Step => {
+                self.step_notes = cycle(&STEP_NOTE_PRESETS, Some(self.step_notes), step);
+            }
+            MenuItem::Length if self.mode == Mode::Stamina => {
+                self.duration = Some(cycle(&STAMINA_PRESETS, self.duration, step));
+            }
+            MenuItem::Length => {
+                let secs = (self.duration.unwrap_or(0.0) + DURATION_STEP * step as f64).min(MAX_DURATION);
+                self.duration = if secs > 0.0 { Some(secs) } else { None };
+            }
+            _ => {}
+        }
+    }
+
+    fn draw(&self, curses: &mut EasyCurses, warnings: &Warnings) {
+        curses.set_color_pair(*COLOR_NORMAL);
+        curses.clear();
+        curses.move_rc(0, 0);
+        curses.set_color_pair(*COLOR_TITLE);
+        curses.print("osu_practice");
+        curses.set_color_pair(*COLOR_NORMAL);
+        for (i, item) in MenuItem::ALL.iter().enumerate() {
+            let text = match item {
+                MenuItem::Mode => match (self.mode, &self.chart) {
+                    (Mode::Chart, Some(chart)) => match &chart.pattern {
+                        Pattern::Chart(c) => format!("Mode: chart ({})", c.name),
+                        _ => "Mode: chart".to_string(),
+                    },
+                    (Mode::Metronome, _) => "Mode: metronome".to_string(),
+                    (Mode::Stamina, _) => "Mode: stamina test".to_string(),
+                    (Mode::Burst, _) => "Mode: burst test".to_string(),
+                    (Mode::Step, _) => "Mode: step test".to_string(),
+                    (Mode::Intervals, _) => "Mode: interval training".to_string(),
+                    (Mode::Training, _) => "Mode: training plan".to_string(),
+                    _ => "Mode: free tapping".to_string(),
+                },
+                MenuItem::Bpm if self.mode == Mode::Metronome || self.mode == Mode::Intervals => {
+                    format!("BPM: {} ({} snap)", self.bpm, self.practice.options.divisor)
+                }
+                MenuItem::Bpm if self.mode == Mode::Step => format!(
+                    "Start BPM: {} (+{} every step, {} snap)",
+                    self.bpm, STEP_BPM, self.practice.options.divisor
+                ),
+                MenuItem::Bpm if self.mode == Mode::Training => format!(
+                    "Target BPM: {} ({} snap)",
+                    self.plan.target_bpm, self.practice.options.divisor
+                ),
+                MenuItem::Bpm => "BPM: -".to_string(),
+                MenuItem::Length if self.mode == Mode::Burst => format!("Taps: {}", self.burst),
+                MenuItem::Length if self.mode == Mode::Step => format!("Notes per step: {}", self.step_notes),
+                MenuItem::Length if self.mode == Mode::Intervals => {
+                    let options = &self.practice.options;
+                    format!(
+                        "Rounds: {} of {} notes, {}s rests",
+                        options.rounds.unwrap_or(DEFAULT_ROUNDS),
+                        self.round_notes,
+                        options.rest.unwrap_or(DEFAULT_REST)
+                    )
+                }
+                MenuItem::Length if self.mode == Mode::Training => {
+                    let drills = DRILLS
+                        .iter()
+                        .map(|d| format!("{} at {} BPM for {}s", d.mode, d.bpm(self.plan.target_bpm), d.duration))
+                        .collect::<Vec<_>>();
+                    format!("Drills: {}", drills.join(", "))
+                }
+                MenuItem::Length => match self.duration {
+                    Some(secs) => format!("Duration: {}s", secs),
+                    None => "Duration: untimed".to_string(),
+                },
+                MenuItem::Technique => format!("Technique: {}", self.practice.options.technique),
+                MenuItem::Start => "Start".to_string(),
+                MenuItem::Keys => "Key bindings".to_string(),
+                MenuItem::Quit => "Quit".to_string(),
+            };
+            let selected = i == self.selected;
+            curses.move_rc(2 + i as i32, 0);
+            curses.set_bold(selected);
+            curses.print(format!("{} {}", if selected { ">" } else { " " }, text));
+        }
+        curses.set_bold(false);
+        curses.move_rc(3 + MenuItem::ALL.len() as i32, 0);
+        curses.print("up/down: choose  left/right: change  enter: select  escape: quit");
+        for (i, warning) in warnings.0.iter().enumerate() {
+            curses.move_rc(5 + MenuItem::ALL.len() as i32 + i as i32, 0);
+            curses.print(warning);
+        }
+        curses.refresh();
+    }
+}
+
+impl SimpleState for MenuState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let mut warnings = Vec::new();
+        let keymap = load_keymap(&mut warnings);
+        let curses = Curses::new(start_curses());
+        if keymap.has_mouse_bindings() {
+            enable_mouse(&mut curses.lock());
+        }
+
+        data.world.remove::<Evdev>();
+        if let Some(path) = &self.practice.options.evdev {
+            match Evdev::open(path) {
+                Ok(evdev) => {
+                    let mut unreadable = keymap
+                        .map
+                        .iter()
+                        .filter(|(binding, slot)| matches!(slot, Slot::Tap(_)) && !has_code(**binding))
+                        .map(|(binding, slot)| format!("{} ({})", binding.name().unwrap_or_default(), slot))
+                        .collect::<Vec<_>>();
+                    if !unreadable.is_empty() {
+                        unreadable.sort();
+                        warnings.push(format!("No key of {} is bound to {}.", path, unreadable.join(", ")));
+                    }
+                    data.world.insert(evdev);
+                }
+                Err(e) => warnings.push(format!("Failed to open {}: {}. Reading taps from the terminal.", path, e)),
+            }
+        }
+
+        data.world.insert(keymap);
+        data.world.insert(Warnings(warnings));
+        data.world.insert(InputPoller::spawn(curses.clone()));
+        data.world.insert(curses);
+        data.world.insert(Screen::Menu);
+        // The practice systems expect these even when nothing is being played.
+        data.world.insert(Tempo::new(self.practice.options.window, self.practice.options.divisor));
+        data.world.insert(HitErrors::new(HitWindows::from_od(self.practice.od())));
+        self.load_plan(data.world);
+        read_events(data.world, &mut self.reader);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        // Gives the terminal back, once nothing reads it anymore.
+        data.world.remove::<InputPoller>();
+        data.world.remove::<Curses>();
+        data.world.remove::<Evdev>();
+    }
+
+    fn on_resume(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        set_screen(data.world, Screen::Menu);
+        self.load_plan(data.world);
+        read_events(data.world, &mut self.reader);
+        self.redraw = true;
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        match std::mem::replace(&mut self.launch, Launch::Menu) {
+            Launch::Practice if self.mode == Mode::Training => return self.start(data.world),
+            Launch::Practice => {
+                let practice = self.practice.clone();
+                // Replays only play once, the menu starts normal sessions afterwards.
+                self.practice.replay = None;
+                self.practice.options.play = None;
+                return Trans::Push(Box::new(PlayingState::new(practice)));
+            }
+            Launch::Keys => return Trans::Push(Box::new(RebindState::default())),
+            Launch::Menu => {}
+        }
+        for ev in read_events(data.world, &mut self.reader) {
+            let input = match ev {
+                InputEvent::Quit => return Trans::Quit,
+                InputEvent::Press(Binding::Input(input)) => input,
+                _ => continue,
+            };
+            self.redraw = true;
+            let count = MenuItem::ALL.len();
+            match input {
+                Input::KeyUp => self.selected = (self.selected + count - 1) % count,
+                Input::KeyDown => self.selected = (self.selected + 1) % count,
+                Input::KeyLeft => self.change(-1),
+                Input::KeyRight => self.change(1),
+                Input::Character('\n') | Input::KeyEnter => match MenuItem::ALL[self.selected] {
+                    MenuItem::Start => return self.start(data.world),
+                    MenuItem::Keys => return Trans::Push(Box::new(RebindState::default())),
+                    MenuItem::Quit => return Trans::Quit,
+                    _ => self.change(1),
+                },
+                _ => {}
+            }
+        }
+        if self.redraw {
+            self.redraw = false;
+            let curses = data.world.read_resource::<Curses>();
+            self.draw(&mut curses.lock(), &data.world.read_resource::<Warnings>());
+        }
+        Trans::None
+    }
+}
+
+/// A practice session, drawn by `CursesRenderSystem`.
+pub struct PlayingState {
+    practice: Practice,
+    reader: Option<ReaderId<InputEvent>>,
+}
+
+impl PlayingState {
+    pub fn new(practice: Practice) -> Self {
+        PlayingState {
+            practice,
+            reader: None,
+        }
+    }
+}
+
+impl SimpleState for PlayingState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let world = data.world;
+        let options = &self.practice.options;
+        set_screen(world, Screen::Playing);
+        world.insert(Clock::Monotonic);
+        world.insert(Stats::default());
+        world.insert(Tempo::new(options.window, options.divisor));
+        world.insert(HitErrors::new(HitWindows::from_od(self.practice.od())));
+        world.insert(Paused::default());
+        world.insert(options.technique);
+        world.read_resource::<InputPoller>().reset_jitter();
+        if let Some(evdev) = world.try_fetch::<Evdev>() {
+            evdev.restart(world.read_resource::<Clock>().now());
+        }
+        world.insert(SessionLimit {
+            duration: options.duration.map(Duration::from_secs_f64),
+            taps: options.burst,
+        });
+
+        world.remove::<Metronome>();
+        world.remove::<StepTest>();
+        if let Some(metronome) = self.practice.metronome.clone() {
+            if let Pattern::Ramp(ramp) = &metronome.pattern {
+                world.insert(StepTest::new(ramp.clone()));
+            }
+            world.insert(metronome);
+        }
+        world.remove::<Player>();
+        if let Some((path, session)) = &self.practice.replay {
+            world.insert(Player::new(path, session.clone()));
+        }
+        world.remove::<Recorder>();
+        if let Some(path) = &options.record {
+            let attempt = world.try_fetch::<RecordedAttempts>().map(|a| a.0).unwrap_or(0) + 1;
+            world.insert(RecordedAttempts(attempt));
+            let path = &attempt_path(path, attempt);
+            let start = world.read_resource::<Clock>().now();
+            match Recorder::create(path, &options.to_args(), start) {
+                Ok(recorder) => world.insert(recorder),
+                Err(e) => warn(world, format!("Failed to record to {}: {}", path, e)),
+            }
+        }
+        read_events(world, &mut self.reader);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        // Closes the recording.
+        data.world.remove::<Recorder>();
+        data.world.remove::<Player>();
+    }
+
+    fn on_resume(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let world = data.world;
+        set_screen(world, Screen::Playing);
+        let since = world.write_resource::<Paused>().0.take();
+        if let Some(since) = since {
+            // Cuts the pause out of the session.
+            let by = world.read_resource::<Clock>().now().duration_since(since);
+            let mut stats = world.write_resource::<Stats>();
+            stats.first_tap = stats.first_tap.map(|tap| tap + by);
+            stats.last_tap = stats.last_tap.map(|tap| tap + by);
+            if let Some(mut metronome) = world.try_fetch_mut::<Metronome>() {
+                metronome.delay(by);
+            }
+            if let Some(mut player) = world.try_fetch_mut::<Player>() {
+                player.delay(by);
+            }
+            if let Some(evdev) = world.try_fetch::<Evdev>() {
+                evdev.delay(by);
+            }
+            if let Some(mut recorder) = world.try_fetch_mut::<Recorder>() {
+                recorder.delay(by);
+            }
+        }
+        read_events(world, &mut self.reader);
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        for ev in read_events(data.world, &mut self.reader) {
+            match ev {
+                InputEvent::Quit => return end_session(data.world, &self.practice),
+                InputEvent::Pause => return Trans::Push(Box::new(PausedState::new(self.practice.clone()))),
+                InputEvent::Reset => return Trans::Switch(Box::new(PlayingState::new(self.practice.clone()))),
+                _ => {}
+            }
+        }
+        let timed_out = {
+            let limit = data.world.read_resource::<SessionLimit>();
+            let stats = data.world.read_resource::<Stats>();
+            let now = data.world.read_resource::<Clock>().now();
+            // Stamina and step tests are over as soon as the stream stops.
+            let test = self.practice.options.stamina || self.practice.options.step.is_some();
+            let stopped = test
+                && stats
+                    .last_tap
+                    .map(|last| now.duration_since(last).as_secs_f64() > BREAK_THRESHOLD)
+                    .unwrap_or(false);
+            let out_of_time = match (limit.duration, stats.first_tap) {
+                (Some(duration), Some(first)) => now.duration_since(first) >= duration,
+                _ => false,
+            };
+            let out_of_taps = limit.taps.map(|taps| is_complete(&stats, taps)).unwrap_or(false);
+            let failed = data.world.try_fetch::<StepTest>().map(|t| t.failed()).unwrap_or(false);
+            stopped || out_of_time || out_of_taps || failed
+        };
+        // Interval training is over after the last round.
+        let rounds_done = data
+            .world
+            .try_fetch::<Metronome>()
+            .map(|metronome| match metronome.pattern {
+                Pattern::Rounds(_) => metronome.is_finished(),
+                _ => false,
+            })
+            .unwrap_or(false);
+        let replayed = data
+            .world
+            .try_fetch::<Player>()
+            .map(|player| player.is_finished())
+            .unwrap_or(false);
+        if timed_out || rounds_done || replayed {
+            return end_session(data.world, &self.practice);
+        }
+        Trans::None
+    }
+}
+
+/// Pushed over `PlayingState`, which `CursesRenderSystem` keeps drawing under an overlay.
+pub struct PausedState {
+    practice: Practice,
+    reader: Option<ReaderId<InputEvent>>,
+}
+
+impl PausedState {
+    pub fn new(practice: Practice) -> Self {
+        PausedState {
+            practice,
+            reader: None,
+        }
+    }
+}
+
+impl SimpleState for PausedState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        set_screen(data.world, Screen::Paused);
+        let now = data.world.read_resource::<Clock>().now();
+        data.world.write_resource::<Paused>().0 = Some(now);
+        read_events(data.world, &mut self.reader);
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        for ev in read_events(data.world, &mut self.reader) {
+            match ev {
+                InputEvent::Pause => return Trans::Pop,
+                InputEvent::Reset => {
+                    return Trans::Sequence(vec![
+                        Trans::Pop,
+                        Trans::Switch(Box::new(PlayingState::new(self.practice.clone()))),
+                    ])
+                }
+                InputEvent::Quit => return Trans::Sequence(vec![Trans::Pop, end_session(data.world, &self.practice)]),
+                _ => {}
+            }
+        }
+        Trans::None
+    }
+}
+
+/// Summary of the session that just ended, saved to the history.
+pub struct ResultsState {
+    practice: Practice,
+    summary: Option<SessionSummary>,
+    timeline: Vec<Option<f64>>,
+    histogram: Option<Histogram>,
+    stamina: Option<StaminaReport>,
+    burst: Option<BurstReport>,
+    step: Option<StepTest>,
+    technique_errors: u32,
+    gallop: Option<String>,
+    drift: Option<DriftReport>,
+    drift_timeline: Vec<Option<f64>>,
+    jitter: Option<Jitter>,
+    messages: Vec<String>,
+    reader: Option<ReaderId<InputEvent>>,
+}
+
+impl ResultsState {
+    pub fn new(practice: Practice) -> Self {
+        ResultsState {
+            practice,
+            summary: None,
+            timeline: Vec::new(),
+            histogram: None,
+            stamina: None,
+            burst: None,
+            step: None,
+            technique_errors: 0,
+            gallop: None,
+            drift: None,
+            drift_timeline: Vec::new(),
+            jitter: None,
+            messages: Vec::new(),
+            reader: None,
+        }
+    }
+
+    /// The drill to play after this one, when following the training plan.
+    fn next_drill(&self) -> Option<Training> {
+        self.practice.training.and_then(|training| training.next())
+    }
+
+    fn draw(&self, curses: &mut EasyCurses, keymap: &Keymap) {
+        curses.set_color_pair(*COLOR_NORMAL);
+        curses.clear();
+        curses.move_rc(0, 0);
+        curses.set_color_pair(*COLOR_TITLE);
+        curses.print("Results");
+        if let Some(training) = self.practice.training {
+            curses.print(format!(" - drill {}/{}", training.drill + 1, DRILLS.len()));
+        }
+        curses.set_color_pair(*COLOR_NORMAL);
+        let mut lines = Vec::new();
+        if let Some(summary) = &self.summary {
+            lines.push(match summary.target_bpm {
+                Some(bpm) => format!("{} at {:.0} BPM, {}", summary.mode, bpm, format_date(summary.date)),
+                None => format!("{}, {}", summary.mode, format_date(summary.date)),
+            });
+            lines.push(format!("{} taps in {:.1}s", summary.taps, summary.duration));
+            lines.push(format!("Score: {}  Max combo: {}", summary.score, summary.max_combo));
+            if let Some(accuracy) = summary.accuracy {
+                lines.push(format!("Accuracy: {:.2}%", accuracy * 100.0));
+            }
+            if let (Some(avg), Some(peak)) = (summary.avg_bpm, summary.peak_bpm) {
+                lines.push(format!("Average BPM: {:.1}  Peak BPM: {:.1}", avg, peak));
+            }
+            if let Some(ur) = summary.unstable_rate {
+                lines.push(format!("UR: {:.2}", ur));
+            }
+        }
+        if let Some(drift) = &self.drift {
+            lines.push(format!(
+                "Drift: {:+.1}ms at the start, {:+.1}ms at the end, {:+.1}ms overall",
+                drift.start, drift.end, drift.overall
+            ));
+            lines.push(match drift.trend() {
+                Tendency::OnTime => format!("Stayed {} through the session", Tendency::of(drift.overall)),
+                trend => format!("Trended towards {} over the session", trend),
+            });
+        }
+        if let Some(jitter) = &self.jitter {
+            lines.push(format!("Input timestamps: {}", jitter));
+        }
+        if let Some(gallop) = &self.gallop {
+            lines.push(format!("Alternation: {}", gallop));
+        }
+        let technique = self.practice.options.technique;
+        if technique != Technique::Any {
+            lines.push(match technique {
+                Technique::Alternate => format!("Full alternate: {} same key presses in a row", self.technique_errors),
+                _ => format!("Singletap: {} presses of another key", self.technique_errors),
+            });
+        }
+        if let Some(report) = &self.burst {
+            match report.bpm {
+                Some(bpm) => lines.push(format!("{} taps in {:.3}s: {:.1} BPM", report.taps, report.time, bpm)),
+                None => lines.push(format!("{} taps", report.taps)),
+            }
+            if let Some(peak) = report.peak_bpm {
+                lines.push(format!("Fastest part: {:.1} BPM", peak));
+            }
+            if let Some(ur) = report.unstable_rate {
+                lines.push(format!("Consistency: {:.2} UR", ur));
+            }
+        }
+        if let Some(test) = &self.step {
+            lines.push(match test.max_bpm() {
+                Some(bpm) => format!(
+                    "Max comfortable stream BPM at length {}: {} ({} snap)",
+                    test.ramp.notes, bpm, test.ramp.divisor
+                ),
+                None => format!("No step of {} notes passed, try a lower start BPM", test.ramp.notes),
+            });
+            let steps = test
+                .completed()
+                .iter()
+                .enumerate()
+                .map(|(i, step)| {
+                    format!(
+                        "{}{}",
+                        test.ramp.bpm(i),
+                        if step.passed() { "" } else { "x" }
+                    )
+                })
+                .collect::<Vec<_>>();
+            if !steps.is_empty() {
+                lines.push(format!("Steps (x failed): {}", steps.join(" ")));
+            }
+            if let Some(last) = test.completed().last() {
+                lines.push(format!(
+                    "Last step: UR {}, {} accuracy",
+                    last.unstable_rate().map(|ur| format!("{:.1}", ur)).unwrap_or_else(|| "-".to_string()),
+                    last.judgements.accuracy().map(|acc| format!("{:.2}%", acc * 100.0)).unwrap_or_else(|| "-".to_string()),
+                ));
+            }
+        }
+        if let Some(report) = &self.stamina {
+            lines.push(match self.practice.options.duration {
+                Some(length) => format!("Streamed for {:.1}s of {}s", report.lasted, length),
+                None => format!("Streamed for {:.1}s", report.lasted),
+            });
+            let bpms = report
+                .segment_bpms
+                .iter()
+                .map(|bpm| bpm.map(|b| format!("{:.0}", b)).unwrap_or_else(|| "-".to_string()))
+                .collect::<Vec<_>>();
+            lines.push(format!("BPM every {:.1}s: {}", report.segment_length(), bpms.join(" ")));
+            if let (Some(first), Some(last), Some(drop)) =
+                (report.first_quarter_bpm, report.last_quarter_bpm, report.speed_drop())
+            {
+                lines.push(format!(
+                    "Speed from the first to the last quarter: {:.1} -> {:.1} BPM ({:+.1}%)",
+                    first,
+                    last,
+                    -drop * 100.0
+                ));
+            }
+            match (report.blowup, report.baseline_ur) {
+                (Some((time, ur)), Some(baseline)) => lines.push(format!(
+                    "UR blew up at {:.1}s: {:.1} against {:.1} in the first quarter",
+                    time, ur, baseline
+                )),
+                (None, Some(baseline)) => lines.push(format!(
+                    "UR held up, never doubling the {:.1} of the first quarter",
+                    baseline
+                )),
+                _ => {}
+            }
+        }
+        for (i, line) in lines.iter().enumerate() {
+            curses.move_rc(2 + i as i32, 0);
+            curses.print(line);
+        }
+
+        let chart_row = 3 + lines.len() as i32;
+        let duration = self.summary.as_ref().map(|s| s.duration).unwrap_or(0.0);
+        draw_bpm_graph(curses, &self.timeline, duration, chart_row, 0);
+        if let Some(histogram) = &self.histogram {
+            draw_interval_histogram(curses, histogram, chart_row, 60);
+        }
+
+        // Below the charts and their axis labels.
+        let mut row = chart_row + GRAPH_HEIGHT + 3;
+        if self.drift.is_some() {
+            draw_drift_graph(curses, &self.drift_timeline, duration, row, 0);
+            row += DRIFT_GRAPH_HALF * 2 + 4;
+        }
+        for message in self.messages.iter() {
+            curses.move_rc(row, 0);
+            curses.print(message);
+            row += 1;
+        }
+        curses.move_rc(row + 1, 0);
+        match self.next_drill() {
+            Some(next) => curses.print(format!(
+                "enter: next drill ({} at {} BPM)  {}: play again  escape: back to the menu",
+                next.drill().mode,
+                next.bpm(),
+                key_names(keymap, Slot::Reset)
+            )),
+            None => curses.print(format!(
+                "{}: play again  enter/escape: back to the menu",
+                key_names(keymap, Slot::Reset)
+            )),
+        };
+        curses.refresh();
+    }
+}
+
+impl SimpleState for ResultsState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let world = data.world;
+        set_screen(world, Screen::Results);
+        let summary = {
+            let stats = world.read_resource::<Stats>();
+            let metronome = world.try_fetch::<Metronome>();
+            let target_bpm = metronome.as_ref().and_then(|m| m.pattern.bpm_at(0.0));
+            let mode = match self.practice.training {
+                Some(training) => training.drill().mode,
+                None => mode_name(&self.practice.options, metronome.as_deref()),
+            };
+            let divisor = world.read_resource::<Tempo>().divisor();
+            if self.practice.options.stamina {
+                self.stamina = Some(StaminaReport::new(&stats, divisor));
+            }
+            if self.practice.options.burst.is_some() {
+                self.burst = Some(BurstReport::new(&stats, divisor));
+            }
+            self.step = world.try_fetch::<StepTest>().map(|test| test.clone());
+            self.technique_errors = stats.technique_errors;
+            self.gallop = stats.gallop.describe();
+            self.drift = DriftReport::new(&stats);
+            self.drift_timeline = stats.drift_timeline(BPM_GRAPH_COLUMNS);
+            // Only the taps read from the terminal depend on how often it's read.
+            if self.practice.replay.is_none() && world.try_fetch::<Evdev>().is_none() {
+                self.jitter = Some(world.read_resource::<InputPoller>().jitter());
+            }
+            self.timeline = stats.bpm_timeline(divisor, BPM_GRAPH_COLUMNS);
+            self.histogram = Histogram::new(&stats.session_intervals, HISTOGRAM_BINS);
+            SessionSummary::new(&stats, divisor, mode, target_bpm)
+        };
+        self.messages = match &self.practice.replay {
+            Some((path, _)) => vec![format!("Replay of {}, not saved to the history.", path)],
+            None => save_session(&summary),
+        };
+        if let (Some(training), None) = (self.practice.training, &self.practice.replay) {
+            if training.next().is_none() {
+                self.messages.extend(review_plan(training));
+            }
+        }
+        self.summary = Some(summary);
+        read_events(world, &mut self.reader);
+        let curses = world.read_resource::<Curses>();
+        self.draw(&mut curses.lock(), &world.read_resource::<Keymap>());
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        for ev in read_events(data.world, &mut self.reader) {
+            match ev {
+                InputEvent::Reset => return Trans::Switch(Box::new(PlayingState::new(self.practice.clone()))),
+                InputEvent::Press(Binding::Input(Input::Character('\n')))
+                | InputEvent::Press(Binding::Input(Input::KeyEnter))
+                    if self.next_drill().is_some() =>
+                {
+                    let practice = self.practice.drill(self.next_drill().unwrap());
+                    return Trans::Switch(Box::new(PlayingState::new(practice)));
+                }
+                InputEvent::Quit
+                | InputEvent::Press(Binding::Input(Input::Character('\n')))
+                | InputEvent::Press(Binding::Input(Input::KeyEnter)) => return Trans::Pop,
+                _ => {}
+            }
+        }
+        Trans::None
+    }
+}
+
+/// Screen to pick a slot, then press the key to bind to it.
+#[derive(Default)]
+pub struct RebindState {
+    /// Slot waiting for its new key.
+    selected: Option<Slot>,
+    message: String,
+    reader: Option<ReaderId<InputEvent>>,
+}
+
+impl RebindState {
+    /// Binds the pressed key to the selected slot and saves the result.
+    fn bind(&mut self, slot: Slot, binding: Binding, keymap: &mut Keymap) {
+        if binding.name().is_none() {
+            self.message = format!("That key can't be bound, press another one for {}.", slot);
+            return;
+        }
+        self.selected = None;
+        self.message = match keymap.bind(slot, binding) {
+            Ok(()) => save_keymap(keymap),
+            Err(e) => e.to_string(),
+        };
+    }
+
+    fn draw(&self, curses: &mut EasyCurses, keymap: &Keymap) {
+        curses.set_color_pair(*COLOR_NORMAL);
+        curses.clear();
+        curses.move_rc(0, 0);
+        curses.set_color_pair(*COLOR_TITLE);
+        curses.print("Key bindings");
+        curses.set_color_pair(*COLOR_NORMAL);
+        for (i, slot) in Slot::ALL.iter().enumerate() {
+            let names = keymap.names(*slot);
+            curses.move_rc(2 + i as i32, 0);
+            curses.set_bold(self.selected == Some(*slot));
+            curses.print(format!(
+                "{}. {}: {}",
+                i + 1,
+                slot,
+                if names.is_empty() { "-".to_string() } else { names.join(" ") }
+            ));
+        }
+        curses.set_bold(false);
+        curses.move_rc(3 + Slot::ALL.len() as i32, 0);
+        match self.selected {
+            Some(slot) => curses.print(format!(
+                "Press the key for {}, delete to unbind it, escape to cancel.",
+                slot
+            )),
+            None => curses.print(format!("Press 1-{} to change a slot, escape to go back.", Slot::ALL.len())),
+        };
+        curses.move_rc(4 + Slot::ALL.len() as i32, 0);
+        curses.print(&self.message);
+        curses.refresh();
+    }
+}
+
+impl SimpleState for RebindState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        set_screen(data.world, Screen::Keys);
+        read_events(data.world, &mut self.reader);
+        let curses = data.world.read_resource::<Curses>();
+        // Lets mouse buttons be bound.
+        enable_mouse(&mut curses.lock());
+        self.draw(&mut curses.lock(), &data.world.read_resource::<Keymap>());
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        let events = read_events(data.world, &mut self.reader);
+        if events.is_empty() {
+            return Trans::None;
+        }
+        let mut keymap = data.world.write_resource::<Keymap>();
+        for ev in events {
+            match (self.selected, ev) {
+                (None, InputEvent::Quit) => return Trans::Pop,
+                (None, InputEvent::Press(Binding::Input(Input::Character(c)))) => {
+                    let slot = c
+                        .to_digit(10)
+                        .and_then(|n| (n as usize).checked_sub(1))
+                        .and_then(|i| Slot::ALL.get(i));
+                    if let Some(slot) = slot {
+                        self.selected = Some(*slot);
+                        self.message.clear();
+                    }
+                }
+                (Some(_), InputEvent::Quit) => self.selected = None,
+                (Some(slot), InputEvent::Press(Binding::Input(Input::KeyDC))) => {
+                    keymap.clear(slot);
+                    self.selected = None;
+                    self.message = save_keymap(&keymap);
+                }
+                (Some(slot), InputEvent::Press(binding)) => self.bind(slot, binding, &mut keymap),
+                _ => {}
+            }
+        }
+        let curses = data.world.read_resource::<Curses>();
+        self.draw(&mut curses.lock(), &keymap);
+        Trans::None
+    }
+}
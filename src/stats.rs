@@ -0,0 +1,756 @@
+use crate::judge::{Hit, Judgements};
+use crate::input::Key;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Gaps longer than this (in seconds) are breaks and start a new stream.
+pub const BREAK_THRESHOLD: f64 = 1.0;
+
+/// Taps the tempo window needs before its BPM counts towards the peak.
+pub const PEAK_MIN_TAPS: usize = 8;
+
+// Taps kept around to compute the spread when the window doesn't bound them.
+const MAX_HISTORY: usize = 256;
+
+// Neighbouring left->right and right->left intervals compared before calling it a gallop.
+const GALLOP_MIN_PAIRS: u32 = 8;
+// How much shorter in percent one direction has to be on average...
+const GALLOP_MIN_PERCENT: f64 = 5.0;
+// ...and in how many of the pairs.
+const GALLOP_MIN_CONSISTENCY: f64 = 0.7;
+
+#[derive(Default)]
+pub struct Stats {
+    pub total: u32,
+    pub combo: u32,
+    pub max_combo: u32,
+    pub score: u64,
+    pub keys: HashMap<Key, KeyStats>,
+    pub intervals: Option<IntervalStats>,
+    pub judgements: Judgements,
+    pub last_hit: Option<Hit>,
+    pub first_tap: Option<Instant>,
+    pub last_tap: Option<Instant>,
+    pub first_key: Option<Key>,
+    pub last_key: Option<Key>,
+    /// Presses that broke the rule of the technique being practiced.
+    pub technique_errors: u32,
+    pub last_technique_error: Option<Instant>,
+    pub gallop: Gallop,
+    /// Every interval in milliseconds between two taps of the same stream, for the whole session.
+    pub session_intervals: Vec<f64>,
+    /// When each of `session_intervals` ended, in seconds since the first tap.
+    pub interval_times: Vec<f64>,
+    /// Milliseconds from the beat of every metronome hit that wasn't a miss, negative when early.
+    pub hit_offsets: Vec<f64>,
+    /// When each of `hit_offsets` was hit, in seconds since the first tap.
+    pub hit_times: Vec<f64>,
+    pub peak_bpm: Option<f64>,
+}
+
+impl Stats {
+    /// Share of the left/right presses that were made with the left key, from 0.0 to 1.0.
+    pub fn balance(&self) -> Option<f64> {
+        let left = self.keys.get(&Key::Left).map(|k| k.presses).unwrap_or(0);
+        let right = self.keys.get(&Key::Right).map(|k| k.presses).unwrap_or(0);
+        if left + right == 0 {
+            return None;
+        }
+        Some(left as f64 / (left + right) as f64)
+    }
+
+    /// Average BPM in each of `columns` equal slices of the session, `None` for slices without a stream.
+    pub fn bpm_timeline(&self, divisor: Divisor, columns: usize) -> Vec<Option<f64>> {
+        column_means(&self.interval_times, &self.session_intervals, columns)
+            .into_iter()
+            .map(|mean| mean.map(|interval| divisor.bpm(1000.0 / interval)))
+            .collect()
+    }
+
+    /// Average hit offset in each of `columns` equal slices of the session, `None` for slices without hits.
+    pub fn drift_timeline(&self, columns: usize) -> Vec<Option<f64>> {
+        column_means(&self.hit_times, &self.hit_offsets, columns)
+    }
+}
+
+/// Mean of the values falling in each of `columns` equal slices of time, up to the last one.
+fn column_means(times: &[f64], values: &[f64], columns: usize) -> Vec<Option<f64>> {
+    let mut sums = vec![(0.0, 0); columns];
+    let duration = times.last().copied().unwrap_or(0.0);
+    if columns == 0 || duration <= 0.0 {
+        return vec![None; columns];
+    }
+    for (time, value) in times.iter().zip(values.iter()) {
+        let col = ((time / duration * columns as f64) as usize).min(columns - 1);
+        sums[col].0 += value;
+        sums[col].1 += 1;
+    }
+    sums.into_iter()
+        .map(|(sum, count)| if count == 0 { None } else { Some(sum / count as f64) })
+        .collect()
+}
+
+/// How many values fall in each of a set of equally wide bins.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    /// Lower bound of the first bin.
+    pub min: f64,
+    pub width: f64,
+    pub counts: Vec<u32>,
+}
+
+impl Histogram {
+    pub fn new(values: &[f64], bins: usize) -> Option<Self> {
+        if values.is_empty() || bins == 0 {
+            return None;
+        }
+        let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let width = if max > min { (max - min) / bins as f64 } else { 1.0 };
+        let mut counts = vec![0; bins];
+        for value in values {
+            let bin = (((value - min) / width) as usize).min(bins - 1);
+            counts[bin] += 1;
+        }
+        Some(Histogram { min, width, counts })
+    }
+
+    /// Upper bound of the last bin.
+    pub fn max(&self) -> f64 {
+        self.min + self.width * self.counts.len() as f64
+    }
+}
+
+/// Spread of the delays between consecutive presses, all in milliseconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntervalStats {
+    pub min: f64,
+    pub max: f64,
+    pub median: f64,
+    pub std_dev: f64,
+    /// 10 times the standard deviation, like osu! does for hit errors.
+    pub unstable_rate: f64,
+}
+
+impl IntervalStats {
+    pub fn from_intervals(intervals: &[f64]) -> Option<Self> {
+        if intervals.len() < 2 {
+            return None;
+        }
+        let mut sorted = intervals.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let len = sorted.len();
+        let median = if len % 2 == 0 {
+            (sorted[len / 2 - 1] + sorted[len / 2]) / 2.0
+        } else {
+            sorted[len / 2]
+        };
+        let mean = sorted.iter().sum::<f64>() / len as f64;
+        let variance = sorted.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / len as f64;
+        let std_dev = variance.sqrt();
+        Some(IntervalStats {
+            min: sorted[0],
+            max: sorted[len - 1],
+            median,
+            std_dev,
+            unstable_rate: std_dev * 10.0,
+        })
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct KeyStats {
+    pub presses: u32,
+    pub last: Option<Instant>,
+    pub interval_sum: f64,
+    pub intervals: u32,
+    /// When the key went down, if it's held and releases are known.
+    pub held_since: Option<Instant>,
+    pub hold_sum: f64,
+    pub holds: u32,
+}
+
+impl KeyStats {
+    pub fn press(&mut self, now: Instant) {
+        self.presses += 1;
+        if let Some(last) = self.last {
+            let delay = now.duration_since(last).as_secs_f64();
+            // Long pauses are breaks, not part of the tapping rhythm.
+            if delay <= BREAK_THRESHOLD {
+                self.interval_sum += delay;
+                self.intervals += 1;
+            }
+        }
+        self.last = Some(now);
+        self.held_since = Some(now);
+    }
+
+    pub fn release(&mut self, now: Instant) {
+        if let Some(since) = self.held_since.take() {
+            self.hold_sum += now.duration_since(since).as_secs_f64();
+            self.holds += 1;
+        }
+    }
+
+    /// Average time in seconds the key was held down for.
+    pub fn avg_hold(&self) -> Option<f64> {
+        if self.holds == 0 {
+            None
+        } else {
+            Some(self.hold_sum / self.holds as f64)
+        }
+    }
+
+    /// Average delay in seconds between two presses of this key.
+    pub fn avg_interval(&self) -> Option<f64> {
+        if self.intervals == 0 {
+            None
+        } else {
+            Some(self.interval_sum / self.intervals as f64)
+        }
+    }
+}
+
+/// Intervals between alternating keys, split by direction, to find uneven alternation.
+#[derive(Default, Clone, Debug)]
+pub struct Gallop {
+    left_right_sum: f64,
+    left_right: u32,
+    right_left_sum: f64,
+    right_left: u32,
+    /// Pairs of neighbouring transitions in both directions, and those where left->right was shorter.
+    pairs: u32,
+    left_right_shorter: u32,
+    /// Direction and interval of the previous transition, if it alternated.
+    last: Option<(Key, f64)>,
+}
+
+impl Gallop {
+    /// Adds the interval in milliseconds between a press of `from` and the press of `to` right after it.
+    pub fn push(&mut self, from: Key, to: Key, interval: f64) {
+        match (from, to) {
+            (Key::Left, Key::Right) => {
+                self.left_right_sum += interval;
+                self.left_right += 1;
+            }
+            (Key::Right, Key::Left) => {
+                self.right_left_sum += interval;
+                self.right_left += 1;
+            }
+            _ => return self.interrupt(),
+        }
+        if let Some((last_from, last_interval)) = self.last {
+            if last_from != from {
+                let (left_right, right_left) = if from == Key::Left {
+                    (interval, last_interval)
+                } else {
+                    (last_interval, interval)
+                };
+                self.pairs += 1;
+                if left_right < right_left {
+                    self.left_right_shorter += 1;
+                }
+            }
+        }
+        self.last = Some((from, interval));
+    }
+
+    /// Stops pairing the next transition with the previous one, after a break or a repeated key.
+    pub fn interrupt(&mut self) {
+        self.last = None;
+    }
+
+    /// Average left->right interval in milliseconds.
+    pub fn left_right(&self) -> Option<f64> {
+        if self.left_right == 0 {
+            None
+        } else {
+            Some(self.left_right_sum / self.left_right as f64)
+        }
+    }
+
+    /// Average right->left interval in milliseconds.
+    pub fn right_left(&self) -> Option<f64> {
+        if self.right_left == 0 {
+            None
+        } else {
+            Some(self.right_left_sum / self.right_left as f64)
+        }
+    }
+
+    /// How much shorter left->right is than right->left, in percent of their mean. Negative if it's longer.
+    pub fn percent(&self) -> Option<f64> {
+        let (left_right, right_left) = (self.left_right()?, self.right_left()?);
+        let mean = (left_right + right_left) / 2.0;
+        if mean <= 0.0 {
+            return None;
+        }
+        Some((right_left - left_right) / mean * 100.0)
+    }
+
+    /// Share of the pairs where the direction that is shorter on average was the shorter one, from 0.5 to 1.0.
+    pub fn consistency(&self) -> Option<f64> {
+        if self.pairs == 0 {
+            return None;
+        }
+        let shorter = if self.percent()? >= 0.0 {
+            self.left_right_shorter
+        } else {
+            self.pairs - self.left_right_shorter
+        };
+        Some(shorter as f64 / self.pairs as f64)
+    }
+
+    /// Whether one direction is consistently shorter than the other.
+    pub fn is_galloping(&self) -> bool {
+        match (self.percent(), self.consistency()) {
+            (Some(percent), Some(consistency)) => {
+                self.pairs >= GALLOP_MIN_PAIRS
+                    && percent.abs() >= GALLOP_MIN_PERCENT
+                    && consistency >= GALLOP_MIN_CONSISTENCY
+            }
+            _ => false,
+        }
+    }
+
+    /// One line description, like "L->R 80.0ms, R->L 90.0ms: gallop of 11.8% (L->R shorter in 90% of pairs)".
+    pub fn describe(&self) -> Option<String> {
+        let (left_right, right_left) = (self.left_right()?, self.right_left()?);
+        let intervals = format!("L->R {:.1}ms, R->L {:.1}ms", left_right, right_left);
+        match (self.percent(), self.consistency()) {
+            (Some(percent), Some(consistency)) if self.is_galloping() => Some(format!(
+                "{}: gallop of {:.1}% ({} shorter in {:.0}% of pairs)",
+                intervals,
+                percent.abs(),
+                if percent > 0.0 { "L->R" } else { "R->L" },
+                consistency * 100.0
+            )),
+            _ => Some(format!("{}: even", intervals)),
+        }
+    }
+}
+
+/// Which taps the tempo is computed from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Window {
+    /// The last N taps.
+    Taps(usize),
+    /// The taps made during the last T seconds.
+    Seconds(f64),
+    /// Exponential moving average of the intervals, using the given smoothing factor (0 to 1).
+    Ema(f64),
+}
+
+impl Window {
+    /// The form accepted by `from_str`.
+    pub fn spec(&self) -> String {
+        match self {
+            Window::Taps(n) => format!("taps:{}", n),
+            Window::Seconds(t) => format!("secs:{}", t),
+            Window::Ema(a) => format!("ema:{}", a),
+        }
+    }
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Window::Taps(16)
+    }
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    /// Parses `taps:16`, `secs:2.5` or `ema:0.2`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        let value = parts
+            .next()
+            .ok_or_else(|| format!("Missing value in window \"{}\"", s))?;
+        match kind {
+            "taps" => match value.parse::<usize>() {
+                Ok(n) if n >= 2 => Ok(Window::Taps(n)),
+                _ => Err(format!("Tap window needs at least 2 taps, got \"{}\"", value)),
+            },
+            "secs" => match value.parse::<f64>() {
+                Ok(t) if t > 0.0 => Ok(Window::Seconds(t)),
+                _ => Err(format!("Time window must be a positive number of seconds, got \"{}\"", value)),
+            },
+            "ema" => match value.parse::<f64>() {
+                Ok(a) if a > 0.0 && a <= 1.0 => Ok(Window::Ema(a)),
+                _ => Err(format!("EMA smoothing factor must be between 0 and 1, got \"{}\"", value)),
+            },
+            _ => Err(format!("Unknown window kind \"{}\", expected taps, secs or ema", kind)),
+        }
+    }
+}
+
+impl std::fmt::Display for Window {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Window::Taps(n) => write!(f, "last {} taps", n),
+            Window::Seconds(t) => write!(f, "last {}s", t),
+            Window::Ema(a) => write!(f, "EMA (alpha {})", a),
+        }
+    }
+}
+
+/// Beat snap divisor, the number of taps per beat.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Divisor(u32);
+
+impl Divisor {
+    pub const ALLOWED: [u32; 6] = [1, 2, 3, 4, 6, 8];
+
+    pub fn new(taps_per_beat: u32) -> Option<Self> {
+        if Self::ALLOWED.contains(&taps_per_beat) {
+            Some(Divisor(taps_per_beat))
+        } else {
+            None
+        }
+    }
+
+    pub fn taps_per_beat(self) -> u32 {
+        self.0
+    }
+
+    /// Converts a tap rate to the beatmap BPM it corresponds to at this snap.
+    pub fn bpm(self, kps: f64) -> f64 {
+        kps * 60.0 / self.0 as f64
+    }
+
+    /// Seconds between two taps at the given beatmap BPM.
+    pub fn interval(self, bpm: f64) -> f64 {
+        60.0 / bpm / self.0 as f64
+    }
+}
+
+/// osu! players count stream BPM in 1/4 notes.
+impl Default for Divisor {
+    fn default() -> Self {
+        Divisor(4)
+    }
+}
+
+impl FromStr for Divisor {
+    type Err = String;
+
+    /// Parses `1/4` or `4`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let n = s.trim_start_matches("1/");
+        n.parse::<u32>()
+            .ok()
+            .and_then(Divisor::new)
+            .ok_or_else(|| format!("Unknown snap divisor \"{}\", expected one of 1/1, 1/2, 1/3, 1/4, 1/6, 1/8", s))
+    }
+}
+
+impl std::fmt::Display for Divisor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "1/{}", self.0)
+    }
+}
+
+/// Rolling tempo computed from the intervals between consecutive taps.
+#[derive(Clone, Debug)]
+pub struct Tempo {
+    window: Window,
+    divisor: Divisor,
+    taps: VecDeque<Instant>,
+    ema: Option<f64>,
+}
+
+impl Tempo {
+    pub fn new(window: Window, divisor: Divisor) -> Self {
+        Tempo {
+            window,
+            divisor,
+            taps: VecDeque::new(),
+            ema: None,
+        }
+    }
+
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    pub fn set_window(&mut self, window: Window) {
+        self.window = window;
+        self.ema = None;
+        self.prune();
+    }
+
+    pub fn divisor(&self) -> Divisor {
+        self.divisor
+    }
+
+    pub fn set_divisor(&mut self, divisor: Divisor) {
+        self.divisor = divisor;
+    }
+
+    pub fn clear(&mut self) {
+        self.taps.clear();
+        self.ema = None;
+    }
+
+    /// Time of the latest tap.
+    pub fn last(&self) -> Option<Instant> {
+        self.taps.back().copied()
+    }
+
+    pub fn push(&mut self, time: Instant) {
+        if let Some(last) = self.last() {
+            let delay = time.duration_since(last).as_secs_f64();
+            if delay > BREAK_THRESHOLD {
+                self.clear();
+            } else if let Window::Ema(alpha) = self.window {
+                self.ema = Some(match self.ema {
+                    Some(ema) => alpha * delay + (1.0 - alpha) * ema,
+                    None => delay,
+                });
+            }
+        }
+        self.taps.push_back(time);
+        self.prune();
+    }
+
+    fn prune(&mut self) {
+        match self.window {
+            Window::Taps(n) => {
+                while self.taps.len() > n {
+                    self.taps.pop_front();
+                }
+            }
+            Window::Seconds(t) => {
+                if let Some(last) = self.last() {
+                    while self
+                        .taps
+                        .front()
+                        .map(|first| last.duration_since(*first).as_secs_f64() > t)
+                        .unwrap_or(false)
+                    {
+                        self.taps.pop_front();
+                    }
+                }
+            }
+            Window::Ema(_) => {}
+        }
+        while self.taps.len() > MAX_HISTORY {
+            self.taps.pop_front();
+        }
+    }
+
+    /// Delays in seconds between each consecutive pair of taps in the window.
+    pub fn intervals(&self) -> Vec<f64> {
+        self.taps
+            .iter()
+            .zip(self.taps.iter().skip(1))
+            .map(|(a, b)| b.duration_since(*a).as_secs_f64())
+            .collect()
+    }
+
+    /// Average delay in seconds between two taps.
+    pub fn interval(&self) -> Option<f64> {
+        if let Window::Ema(_) = self.window {
+            return self.ema;
+        }
+        let len = self.taps.len();
+        if len < 2 {
+            return None;
+        }
+        let span = self.taps[len - 1].duration_since(self.taps[0]).as_secs_f64();
+        if span <= 0.0 {
+            return None;
+        }
+        Some(span / (len - 1) as f64)
+    }
+
+    /// Keys per second.
+    pub fn kps(&self) -> Option<f64> {
+        self.interval().map(|i| 1.0 / i)
+    }
+
+    /// Beatmap BPM at the selected snap divisor.
+    pub fn bpm(&self) -> Option<f64> {
+        self.kps().map(|kps| self.divisor.bpm(kps))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    // Pushes taps at the given milliseconds after the start.
+    fn tap(tempo: &mut Tempo, start: Instant, times: &[u64]) {
+        for ms in times {
+            tempo.push(start + Duration::from_millis(*ms));
+        }
+    }
+
+    #[test]
+    fn taps_window_averages_the_last_taps() {
+        let mut tempo = Tempo::new(Window::Taps(3), Divisor::default());
+        let start = Instant::now();
+        assert_eq!(tempo.interval(), None);
+        tap(&mut tempo, start, &[0, 100, 200, 400, 600]);
+        assert!(close(tempo.interval().unwrap(), 0.2));
+        assert_eq!(tempo.intervals().len(), 2);
+    }
+
+    #[test]
+    fn seconds_window_drops_old_taps() {
+        let mut tempo = Tempo::new(Window::Seconds(0.5), Divisor::default());
+        let start = Instant::now();
+        tap(&mut tempo, start, &[0, 300, 400, 500, 600, 700]);
+        // The tap at 0 is more than half a second before the last one.
+        assert_eq!(tempo.intervals().len(), 4);
+        assert!(close(tempo.interval().unwrap(), 0.1));
+    }
+
+    #[test]
+    fn ema_window_smooths_the_intervals() {
+        let mut tempo = Tempo::new(Window::Ema(0.5), Divisor::default());
+        let start = Instant::now();
+        tap(&mut tempo, start, &[0, 100]);
+        assert!(close(tempo.interval().unwrap(), 0.1));
+        tap(&mut tempo, start, &[300]);
+        assert!(close(tempo.interval().unwrap(), 0.15));
+    }
+
+    #[test]
+    fn break_starts_over() {
+        for window in &[Window::Taps(16), Window::Seconds(5.0), Window::Ema(0.5)] {
+            let mut tempo = Tempo::new(*window, Divisor::default());
+            let start = Instant::now();
+            tap(&mut tempo, start, &[0, 100, 200, 1500]);
+            assert_eq!(tempo.interval(), None, "{}", window);
+            tap(&mut tempo, start, &[1750]);
+            assert!(close(tempo.interval().unwrap(), 0.25), "{}", window);
+        }
+    }
+
+    #[test]
+    fn bpm_at_the_snap_divisor() {
+        let mut tempo = Tempo::new(Window::Taps(16), Divisor::default());
+        let start = Instant::now();
+        tap(&mut tempo, start, &[0, 125, 250]);
+        assert!(close(tempo.kps().unwrap(), 8.0));
+        assert!(close(tempo.bpm().unwrap(), 120.0));
+
+        let half = Divisor::new(2).unwrap();
+        assert!(close(half.bpm(8.0), 240.0));
+        assert!(close(half.interval(240.0), 0.125));
+        assert!(close(Divisor::default().interval(Divisor::default().bpm(7.0)), 1.0 / 7.0));
+    }
+
+    #[test]
+    fn parse_windows() {
+        assert_eq!("taps:8".parse::<Window>(), Ok(Window::Taps(8)));
+        assert_eq!("secs:2.5".parse::<Window>(), Ok(Window::Seconds(2.5)));
+        assert_eq!("ema:0.2".parse::<Window>(), Ok(Window::Ema(0.2)));
+        for spec in &["taps", "taps:1", "taps:x", "secs:0", "secs:-1", "ema:0", "ema:1.5", "bars:4", ""] {
+            assert!(spec.parse::<Window>().is_err(), "{}", spec);
+        }
+        let window = Window::Seconds(2.5);
+        assert_eq!(window.spec().parse::<Window>(), Ok(window));
+    }
+
+    #[test]
+    fn parse_divisors() {
+        assert_eq!("1/4".parse::<Divisor>(), Ok(Divisor::default()));
+        assert_eq!("3".parse::<Divisor>(), Divisor::new(3).ok_or_else(String::new));
+        for spec in &["1/5", "0", "1/", "quarter", "2/4"] {
+            assert!(spec.parse::<Divisor>().is_err(), "{}", spec);
+        }
+    }
+
+    // Alternates left->right and right->left transitions of the given intervals, starting with left->right.
+    fn alternate(gallop: &mut Gallop, transitions: usize, left_right: &[f64], right_left: f64) {
+        for i in 0..transitions {
+            if i % 2 == 0 {
+                gallop.push(Key::Left, Key::Right, left_right[i / 2 % left_right.len()]);
+            } else {
+                gallop.push(Key::Right, Key::Left, right_left);
+            }
+        }
+    }
+
+    #[test]
+    fn gallop_of_the_shorter_direction() {
+        let mut gallop = Gallop::default();
+        alternate(&mut gallop, 20, &[80.0], 90.0);
+        assert_eq!((gallop.left_right(), gallop.right_left()), (Some(80.0), Some(90.0)));
+        assert!(close(gallop.percent().unwrap(), 10.0 / 85.0 * 100.0));
+        assert_eq!(gallop.consistency(), Some(1.0));
+        assert!(gallop.is_galloping());
+        assert_eq!(
+            gallop.describe().unwrap(),
+            "L->R 80.0ms, R->L 90.0ms: gallop of 11.8% (L->R shorter in 100% of pairs)"
+        );
+
+        let mut gallop = Gallop::default();
+        alternate(&mut gallop, 20, &[90.0], 80.0);
+        assert!(gallop.percent().unwrap() < 0.0);
+        assert_eq!(gallop.consistency(), Some(1.0));
+        assert_eq!(
+            gallop.describe().unwrap(),
+            "L->R 90.0ms, R->L 80.0ms: gallop of 11.8% (R->L shorter in 100% of pairs)"
+        );
+    }
+
+    #[test]
+    fn even_alternation_is_no_gallop() {
+        let mut gallop = Gallop::default();
+        assert_eq!(gallop.describe(), None);
+        alternate(&mut gallop, 20, &[85.0], 85.0);
+        assert_eq!(gallop.percent(), Some(0.0));
+        assert!(!gallop.is_galloping());
+        assert_eq!(gallop.describe().unwrap(), "L->R 85.0ms, R->L 85.0ms: even");
+
+        // Less than the minimum difference.
+        let mut gallop = Gallop::default();
+        alternate(&mut gallop, 20, &[86.0], 90.0);
+        assert_eq!(gallop.consistency(), Some(1.0));
+        assert!(!gallop.is_galloping());
+    }
+
+    #[test]
+    fn gallop_needs_enough_pairs() {
+        let mut gallop = Gallop::default();
+        alternate(&mut gallop, GALLOP_MIN_PAIRS as usize, &[80.0], 90.0);
+        assert!(!gallop.is_galloping());
+        alternate(&mut gallop, 1, &[80.0], 90.0);
+        assert!(gallop.is_galloping());
+    }
+
+    #[test]
+    fn gallop_needs_consistent_pairs() {
+        // Shorter on average, but only in half of the pairs.
+        let mut gallop = Gallop::default();
+        alternate(&mut gallop, 40, &[70.0, 100.0], 90.0);
+        assert!(gallop.percent().unwrap() >= GALLOP_MIN_PERCENT);
+        assert!(gallop.consistency().unwrap() < GALLOP_MIN_CONSISTENCY);
+        assert!(!gallop.is_galloping());
+    }
+
+    #[test]
+    fn interrupted_transitions_are_not_paired() {
+        let mut gallop = Gallop::default();
+        for _ in 0..10 {
+            gallop.push(Key::Left, Key::Right, 80.0);
+            gallop.push(Key::Right, Key::Right, 100.0);
+            gallop.push(Key::Right, Key::Left, 90.0);
+            gallop.interrupt();
+        }
+        assert_eq!((gallop.left_right(), gallop.right_left()), (Some(80.0), Some(90.0)));
+        assert_eq!(gallop.consistency(), None);
+        assert!(!gallop.is_galloping());
+    }
+}
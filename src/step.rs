@@ -0,0 +1,248 @@
+use crate::judge::{Hit, Judgement, Judgements};
+use crate::stats::{Divisor, IntervalStats};
+
+/// Notes per step offered in the menu.
+pub const STEP_NOTE_PRESETS: [usize; 3] = [16, 32, 64];
+pub const DEFAULT_STEP_NOTES: usize = 32;
+/// BPM added after every step.
+pub const STEP_BPM: f64 = 10.0;
+
+// A step fails once the UR of its hits goes over this, or its accuracy under the next one.
+const MAX_STEP_UR: f64 = 200.0;
+const MIN_STEP_ACCURACY: f64 = 0.9;
+
+/// A steady stream that gets `step` BPM faster every `notes` notes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ramp {
+    pub start: f64,
+    pub step: f64,
+    pub notes: usize,
+    pub divisor: Divisor,
+}
+
+impl Ramp {
+    pub fn new(start: f64, notes: usize, divisor: Divisor) -> Self {
+        Ramp {
+            start,
+            step: STEP_BPM,
+            notes: notes.max(1),
+            divisor,
+        }
+    }
+
+    /// Index of the step the given beat belongs to.
+    pub fn step_of(&self, beat: usize) -> usize {
+        beat / self.notes
+    }
+
+    /// Beatmap BPM of the given step.
+    pub fn bpm(&self, step: usize) -> f64 {
+        self.start + self.step * step as f64
+    }
+
+    // Milliseconds between two notes of the given step.
+    fn interval(&self, step: usize) -> f64 {
+        self.divisor.interval(self.bpm(step)) * 1000.0
+    }
+
+    /// Milliseconds from the first beat to the given beat.
+    pub fn beat_time(&self, beat: usize) -> f64 {
+        let step = self.step_of(beat);
+        let steps = (0..step).map(|s| self.interval(s) * self.notes as f64).sum::<f64>();
+        steps + (beat - step * self.notes) as f64 * self.interval(step)
+    }
+
+    /// Beats since the first one at the given number of milliseconds, with the fraction of the current beat.
+    pub fn position(&self, time: f64) -> f64 {
+        let mut step = 0;
+        let mut start = 0.0;
+        loop {
+            let length = self.interval(step) * self.notes as f64;
+            if time < start + length {
+                return (step * self.notes) as f64 + (time - start) / self.interval(step);
+            }
+            start += length;
+            step += 1;
+        }
+    }
+
+    pub fn bpm_at(&self, time: f64) -> f64 {
+        self.bpm(self.step_of(self.position(time.max(0.0)) as usize))
+    }
+}
+
+/// How one step of the test went.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StepResult {
+    pub judgements: Judgements,
+    /// Milliseconds from the beat of every hit.
+    offsets: Vec<f64>,
+}
+
+impl StepResult {
+    pub fn unstable_rate(&self) -> Option<f64> {
+        IntervalStats::from_intervals(&self.offsets).map(|i| i.unstable_rate)
+    }
+
+    pub fn passed(&self) -> bool {
+        let steady = self.unstable_rate().map(|ur| ur <= MAX_STEP_UR).unwrap_or(false);
+        let accurate = self.judgements.accuracy().map(|acc| acc >= MIN_STEP_ACCURACY).unwrap_or(false);
+        steady && accurate
+    }
+}
+
+/// Hits of a step test sorted by step, to find the fastest BPM that can be held for a whole step.
+#[derive(Clone, Debug)]
+pub struct StepTest {
+    pub ramp: Ramp,
+    pub steps: Vec<StepResult>,
+    next_beat: usize,
+}
+
+impl StepTest {
+    pub fn new(ramp: Ramp) -> Self {
+        StepTest {
+            ramp,
+            steps: Vec::new(),
+            next_beat: 0,
+        }
+    }
+
+    fn result(&mut self, beat: usize) -> &mut StepResult {
+        let step = self.ramp.step_of(beat);
+        if self.steps.len() <= step {
+            self.steps.resize_with(step + 1, StepResult::default);
+        }
+        &mut self.steps[step]
+    }
+
+    /// Adds a hit of the metronome, which is now waiting for `next_beat`.
+    pub fn push(&mut self, hit: &Hit, next_beat: usize) {
+        if hit.judgement == Judgement::Miss {
+            // Taps too far from any beat don't use one up.
+            self.result(next_beat).judgements.add(Judgement::Miss);
+            return;
+        }
+        let beat = next_beat - 1;
+        for skipped in beat - hit.skipped as usize..beat {
+            self.result(skipped).judgements.add(Judgement::Miss);
+        }
+        let result = self.result(beat);
+        result.judgements.add(hit.judgement);
+        result.offsets.push(hit.offset);
+        self.next_beat = next_beat;
+    }
+
+    /// Step the metronome is at now.
+    pub fn current_step(&self) -> usize {
+        self.ramp.step_of(self.next_beat)
+    }
+
+    /// Steps whose every beat was hit or skipped.
+    pub fn completed(&self) -> &[StepResult] {
+        &self.steps[..self.current_step().min(self.steps.len())]
+    }
+
+    /// Whether the last completed step went past the thresholds, which ends the test.
+    pub fn failed(&self) -> bool {
+        self.completed().last().map(|step| !step.passed()).unwrap_or(false)
+    }
+
+    /// BPM of the fastest completed step that passed.
+    pub fn max_bpm(&self) -> Option<f64> {
+        self.completed()
+            .iter()
+            .enumerate()
+            .filter(|(_, step)| step.passed())
+            .map(|(i, _)| self.ramp.bpm(i))
+            .last()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp() -> Ramp {
+        // 75ms between notes for the first step, then 60 / 210 / 4 seconds.
+        Ramp::new(200.0, 4, Divisor::default())
+    }
+
+    fn hit(judgement: Judgement, offset: f64, skipped: u32) -> Hit {
+        Hit {
+            judgement,
+            offset,
+            skipped,
+        }
+    }
+
+    // Hits every beat of a step, alternating between `offset` early and late.
+    fn play_step(test: &mut StepTest, step: usize, offset: f64) {
+        for beat in step * test.ramp.notes..(step + 1) * test.ramp.notes {
+            let offset = if beat % 2 == 0 { -offset } else { offset };
+            test.push(&hit(Judgement::Great, offset, 0), beat + 1);
+        }
+    }
+
+    #[test]
+    fn beat_times_and_positions_agree() {
+        let ramp = ramp();
+        assert_eq!(ramp.beat_time(0), 0.0);
+        assert!((ramp.beat_time(4) - 300.0).abs() < 1e-9);
+        assert!((ramp.beat_time(5) - (300.0 + 60000.0 / 210.0 / 4.0)).abs() < 1e-9);
+        for beat in 0..40 {
+            assert!((ramp.position(ramp.beat_time(beat)) - beat as f64).abs() < 1e-9, "beat {}", beat);
+            let halfway = (ramp.beat_time(beat) + ramp.beat_time(beat + 1)) / 2.0;
+            assert!((ramp.position(halfway) - (beat as f64 + 0.5)).abs() < 1e-9, "beat {}", beat);
+        }
+        assert_eq!(ramp.bpm_at(-10.0), 200.0);
+        assert_eq!(ramp.bpm_at(299.0), 200.0);
+        assert_eq!(ramp.bpm_at(300.0), 210.0);
+        assert_eq!(ramp.bpm_at(ramp.beat_time(12)), 230.0);
+    }
+
+    #[test]
+    fn skipped_beats_are_misses() {
+        let mut test = StepTest::new(ramp());
+        test.push(&hit(Judgement::Great, 0.0, 0), 1);
+        // Beats 1 and 2 went by, beat 3 was hit.
+        test.push(&hit(Judgement::Good, 10.0, 2), 4);
+        assert_eq!(test.current_step(), 1);
+        let step = &test.completed()[0];
+        assert_eq!((step.judgements.great, step.judgements.good, step.judgements.miss), (1, 1, 2));
+        // A tap far from any beat counts against the step it was waiting in, without using a beat up.
+        test.push(&hit(Judgement::Miss, 0.0, 0), 4);
+        assert_eq!(test.steps[1].judgements.miss, 1);
+        assert_eq!(test.current_step(), 1);
+        // Skipping over the end of a step puts the misses in the steps they belong to.
+        test.push(&hit(Judgement::Great, 0.0, 5), 10);
+        assert_eq!(test.steps[1].judgements.miss, 5);
+        assert_eq!(test.steps[2].judgements.miss, 1);
+        assert_eq!(test.steps[2].judgements.great, 1);
+        assert_eq!(test.completed().len(), 2);
+    }
+
+    #[test]
+    fn max_bpm_after_a_failed_step() {
+        let mut test = StepTest::new(ramp());
+        assert_eq!(test.max_bpm(), None);
+        play_step(&mut test, 0, 5.0);
+        assert!(!test.failed());
+        play_step(&mut test, 1, 8.0);
+        assert!(!test.failed());
+        assert_eq!(test.max_bpm(), Some(210.0));
+        // A UR of 300 is too unsteady.
+        play_step(&mut test, 2, 30.0);
+        assert!(test.failed());
+        assert_eq!(test.max_bpm(), Some(210.0));
+    }
+
+    #[test]
+    fn inaccurate_steps_fail() {
+        let mut test = StepTest::new(ramp());
+        test.push(&hit(Judgement::Great, 0.0, 0), 1);
+        test.push(&hit(Judgement::Great, 1.0, 2), 4);
+        assert!(test.failed());
+        assert_eq!(test.max_bpm(), None);
+    }
+}
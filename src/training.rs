@@ -0,0 +1,326 @@
+use crate::history::{data_dir, History, SessionSummary};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// History mode of the drill at the target BPM, the one the plan is adjusted from.
+pub const TRAINING_MODE: &str = "training";
+pub const DEFAULT_TARGET_BPM: f64 = 160.0;
+/// BPM the target moves by after a review.
+pub const TARGET_STEP: f64 = 5.0;
+
+// Recent sessions at the target looked at when reviewing the plan.
+const REVIEW_SESSIONS: usize = 3;
+// All of the reviewed sessions have to be this good on average to raise the target...
+const RAISE_ACCURACY: f64 = 0.95;
+const RAISE_UR: f64 = 120.0;
+// ...and this bad to lower it.
+const LOWER_ACCURACY: f64 = 0.85;
+const LOWER_UR: f64 = 200.0;
+
+/// One metronome session of a training program.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Drill {
+    /// Mode the drill is saved as in the history.
+    pub mode: &'static str,
+    /// BPM as a share of the target.
+    pub speed: f64,
+    /// Length in seconds.
+    pub duration: f64,
+}
+
+impl Drill {
+    pub fn bpm(&self, target: f64) -> f64 {
+        (target * self.speed).round()
+    }
+}
+
+/// Drills of a training session, in order.
+pub const DRILLS: [Drill; 3] = [
+    Drill {
+        mode: "training warm-up",
+        speed: 0.9,
+        duration: 20.0,
+    },
+    Drill {
+        mode: TRAINING_MODE,
+        speed: 1.0,
+        duration: 30.0,
+    },
+    Drill {
+        mode: "training push",
+        speed: 1.05,
+        duration: 15.0,
+    },
+];
+
+#[derive(Debug)]
+pub enum PlanError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+    NoDataDir,
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanError::Io(e) => write!(f, "Failed to access training plan: {}", e),
+            PlanError::Yaml(e) => write!(f, "Invalid training plan: {}", e),
+            PlanError::NoDataDir => write!(f, "Neither XDG_DATA_HOME nor HOME is set"),
+        }
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+impl From<std::io::Error> for PlanError {
+    fn from(e: std::io::Error) -> Self {
+        PlanError::Io(e)
+    }
+}
+
+impl From<serde_yaml::Error> for PlanError {
+    fn from(e: serde_yaml::Error) -> Self {
+        PlanError::Yaml(e)
+    }
+}
+
+/// Where the training program is at, kept in the data directory next to the history.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrainingPlan {
+    pub target_bpm: f64,
+}
+
+impl Default for TrainingPlan {
+    fn default() -> Self {
+        TrainingPlan {
+            target_bpm: DEFAULT_TARGET_BPM,
+        }
+    }
+}
+
+fn plan_path() -> Result<PathBuf, PlanError> {
+    Ok(data_dir().ok_or(PlanError::NoDataDir)?.join("training.yml"))
+}
+
+impl TrainingPlan {
+    /// The plan in the data directory, or a new one if there is none yet.
+    pub fn load_default() -> Result<Self, PlanError> {
+        TrainingPlan::load(&plan_path()?)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PlanError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Ok(serde_yaml::from_str(&text)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(TrainingPlan::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save_default(&self) -> Result<(), PlanError> {
+        self.save(&plan_path()?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), PlanError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Looks at the latest sessions at the target in the history to pick the next target.
+    pub fn review(&self, history: &History) -> Review {
+        let sessions = history
+            .sessions
+            .iter()
+            .rev()
+            .filter(|s| s.mode == TRAINING_MODE && s.target_bpm == Some(self.target_bpm.round()))
+            .take(REVIEW_SESSIONS)
+            .collect::<Vec<_>>();
+        let mean = |value: fn(&SessionSummary) -> Option<f64>| {
+            let values = sessions.iter().filter_map(|s| value(s)).collect::<Vec<_>>();
+            if values.is_empty() {
+                None
+            } else {
+                Some(values.iter().sum::<f64>() / values.len() as f64)
+            }
+        };
+        let accuracy = mean(|s| s.accuracy);
+        let unstable_rate = mean(|s| s.unstable_rate);
+        let struggling = accuracy.map(|acc| acc < LOWER_ACCURACY).unwrap_or(false)
+            || unstable_rate.map(|ur| ur > LOWER_UR).unwrap_or(false);
+        let consistent = sessions.len() >= REVIEW_SESSIONS
+            && accuracy.map(|acc| acc >= RAISE_ACCURACY).unwrap_or(false)
+            && unstable_rate.map(|ur| ur <= RAISE_UR).unwrap_or(false);
+        let target_bpm = if struggling {
+            (self.target_bpm - TARGET_STEP).max(TARGET_STEP)
+        } else if consistent {
+            self.target_bpm + TARGET_STEP
+        } else {
+            self.target_bpm
+        };
+        Review {
+            sessions: sessions.len(),
+            accuracy,
+            unstable_rate,
+            target_bpm,
+        }
+    }
+}
+
+/// What the recent sessions at the target looked like, and the target they lead to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Review {
+    pub sessions: usize,
+    pub accuracy: Option<f64>,
+    pub unstable_rate: Option<f64>,
+    pub target_bpm: f64,
+}
+
+/// A training session in progress: the target it was started with and the drill being played.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Training {
+    pub target_bpm: f64,
+    pub drill: usize,
+}
+
+impl Training {
+    pub fn new(target_bpm: f64) -> Self {
+        Training { target_bpm, drill: 0 }
+    }
+
+    pub fn drill(&self) -> &'static Drill {
+        &DRILLS[self.drill]
+    }
+
+    pub fn bpm(&self) -> f64 {
+        self.drill().bpm(self.target_bpm)
+    }
+
+    /// The following drill, `None` after the last one.
+    pub fn next(&self) -> Option<Training> {
+        if self.drill + 1 < DRILLS.len() {
+            Some(Training {
+                drill: self.drill + 1,
+                ..*self
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(mode: &str, target_bpm: f64, accuracy: f64, unstable_rate: f64) -> SessionSummary {
+        SessionSummary {
+            date: 1_600_000_000,
+            mode: mode.to_string(),
+            target_bpm: Some(target_bpm),
+            duration: 30.0,
+            taps: 360,
+            max_combo: 360,
+            score: 0,
+            avg_bpm: Some(target_bpm),
+            peak_bpm: None,
+            unstable_rate: Some(unstable_rate),
+            accuracy: Some(accuracy),
+        }
+    }
+
+    fn review(sessions: Vec<SessionSummary>) -> Review {
+        let history = History {
+            sessions,
+            ..Default::default()
+        };
+        TrainingPlan { target_bpm: 180.0 }.review(&history)
+    }
+
+    #[test]
+    fn raised_after_consistent_sessions() {
+        let review = review(vec![
+            session(TRAINING_MODE, 180.0, 0.94, 105.0),
+            session(TRAINING_MODE, 180.0, 0.97, 120.0),
+            session(TRAINING_MODE, 180.0, 0.96, 129.0),
+        ]);
+        assert_eq!(review.sessions, 3);
+        assert!((review.accuracy.unwrap() - 0.9566).abs() < 1e-3);
+        assert!((review.unstable_rate.unwrap() - 118.0).abs() < 1e-9);
+        assert_eq!(review.target_bpm, 185.0);
+    }
+
+    #[test]
+    fn only_the_latest_sessions_at_the_target_count() {
+        let review = review(vec![
+            session(TRAINING_MODE, 180.0, 0.5, 300.0),
+            session(TRAINING_MODE, 180.0, 0.97, 100.0),
+            session("training push", 189.0, 0.5, 300.0),
+            session(TRAINING_MODE, 175.0, 0.5, 300.0),
+            session(TRAINING_MODE, 180.0, 0.97, 100.0),
+            session(TRAINING_MODE, 180.0, 0.97, 100.0),
+        ]);
+        assert_eq!(review.sessions, 3);
+        assert_eq!(review.target_bpm, 185.0);
+    }
+
+    #[test]
+    fn not_raised_without_enough_sessions() {
+        let review = review(vec![
+            session(TRAINING_MODE, 180.0, 0.99, 80.0),
+            session(TRAINING_MODE, 180.0, 0.99, 80.0),
+        ]);
+        assert_eq!(review.sessions, 2);
+        assert_eq!(review.target_bpm, 180.0);
+        assert_eq!(self::review(Vec::new()).target_bpm, 180.0);
+    }
+
+    #[test]
+    fn not_raised_when_almost_consistent() {
+        let sessions = |accuracy, unstable_rate| vec![session(TRAINING_MODE, 180.0, accuracy, unstable_rate); 3];
+        assert_eq!(review(sessions(0.94, 100.0)).target_bpm, 180.0);
+        assert_eq!(review(sessions(0.97, 121.0)).target_bpm, 180.0);
+    }
+
+    #[test]
+    fn lowered_when_struggling() {
+        let inaccurate = review(vec![session(TRAINING_MODE, 180.0, 0.84, 100.0)]);
+        assert_eq!(inaccurate.target_bpm, 175.0);
+        let unstable = review(vec![
+            session(TRAINING_MODE, 180.0, 0.97, 150.0),
+            session(TRAINING_MODE, 180.0, 0.97, 260.0),
+        ]);
+        assert_eq!(unstable.target_bpm, 175.0);
+        let borderline = review(vec![session(TRAINING_MODE, 180.0, 0.85, 200.0)]);
+        assert_eq!(borderline.target_bpm, 180.0);
+    }
+
+    #[test]
+    fn never_lowered_to_nothing() {
+        let history = History {
+            sessions: vec![session(TRAINING_MODE, 5.0, 0.5, 300.0)],
+            ..Default::default()
+        };
+        assert_eq!(TrainingPlan { target_bpm: 5.0 }.review(&history).target_bpm, 5.0);
+    }
+
+    #[test]
+    fn drill_bpms() {
+        let mut training = Some(Training::new(200.0));
+        let mut drills = Vec::new();
+        while let Some(current) = training {
+            drills.push((current.drill().mode, current.bpm()));
+            training = current.next();
+        }
+        assert_eq!(
+            drills,
+            vec![("training warm-up", 180.0), (TRAINING_MODE, 200.0), ("training push", 210.0)]
+        );
+        // Rounded to whole BPM.
+        assert_eq!(Training::new(171.0).bpm(), 154.0);
+        assert_eq!(DRILLS[2].bpm(171.0), 180.0);
+    }
+}